@@ -0,0 +1,61 @@
+//! This example runs the six-position accelerometer calibration, prints the
+//! serialized result via itm and then tracks the orientation with the
+//! calibration applied.
+//!
+//! Follow the LED prompts: turn the edge of the lit LED up, lay the board
+//! flat when all LEDs are lit and upside down when none are. Hold it still
+//! until all LEDs blink.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use accelerometer::orientation::Tracker;
+use accelerometer::Accelerometer;
+
+use board::calibration::{self, AccelCalibration};
+use board::hal::delay::Delay;
+use board::Board;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+        let mut accelerometer = board.accelerometer;
+        let mut delay = Delay::new(board.core.SYST, board.clocks);
+        let mut itm = board.core.ITM;
+
+        let result =
+            calibration::calibrate_accelerometer(&mut accelerometer, &mut leds, &mut delay, true);
+
+        match result {
+            Ok(Some(calibration)) => {
+                iprintln!(&mut itm.stim[0], "{:?}", calibration);
+                // Store these bytes in flash and restore them with `from_bytes`
+                let bytes = calibration.to_bytes();
+                iprintln!(&mut itm.stim[0], "{:?}", &bytes[..]);
+
+                accelerometer.set_calibration(AccelCalibration::from_bytes(&bytes));
+            }
+            Ok(None) => iprintln!(&mut itm.stim[0], "calibration failed: degenerate"),
+            Err(error) => iprintln!(&mut itm.stim[0], "calibration failed: {:?}", error),
+        }
+
+        let mut tracker = Tracker::new(0.2);
+
+        loop {
+            let acceleration = accelerometer.accel_norm().unwrap();
+            let orientation = tracker.update(acceleration);
+
+            iprintln!(&mut itm.stim[0], "{:?}", orientation);
+            leds.point_to_vector(acceleration.x, acceleration.y);
+        }
+    }
+
+    loop {}
+}
@@ -0,0 +1,106 @@
+//! This example streams accelerometer samples at 1.344 kHz through the FIFO.
+//!
+//! The FIFO watermark interrupt on INT1 (PE4) wakes the CPU, which then reads
+//! all pending samples in one burst and prints their average via itm.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use accelerometer::vector::I16x3;
+
+use board::accelerometer::{DataRate, FifoMode, InterruptPin, Interrupts, FIFO_DEPTH};
+use board::exti::InterruptLine;
+use board::hal::gpio::gpioe::PE4;
+use board::hal::gpio::{Edge, Floating, Input};
+use board::hal::interrupt;
+use board::hal::stm32;
+use board::Board;
+
+static INT1: Mutex<RefCell<Option<InterruptLine<PE4<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut accelerometer = board.accelerometer;
+        let mut device = board.device;
+        let mut itm = board.core.ITM;
+
+        accelerometer.set_data_rate(DataRate::Khz1_344).unwrap();
+        accelerometer.set_fifo_mode(FifoMode::Stream, 16).unwrap();
+        accelerometer
+            .route_interrupts(
+                InterruptPin::Int1,
+                Interrupts {
+                    fifo_watermark: true,
+                    ..Interrupts::default()
+                },
+            )
+            .unwrap();
+
+        let line = InterruptLine::new(
+            board.mems_interrupts.accel_int1,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        cortex_m::interrupt::free(|cs| INT1.borrow(cs).replace(Some(line)));
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI4);
+        }
+
+        let mut samples = [I16x3::new(0, 0, 0); FIFO_DEPTH];
+
+        loop {
+            // INT1 stays high until the FIFO is drained below the watermark,
+            // so read everything that is pending after every wake-up
+            let count = accelerometer.read_fifo(&mut samples).unwrap();
+            if count > 0 {
+                let mut sum = (0i32, 0i32, 0i32);
+                for sample in &samples[..count] {
+                    sum.0 += i32::from(sample.x);
+                    sum.1 += i32::from(sample.y);
+                    sum.2 += i32::from(sample.z);
+                }
+                let n = count as i32;
+                let average = accelerometer.to_g(I16x3::new(
+                    (sum.0 / n) as i16,
+                    (sum.1 / n) as i16,
+                    (sum.2 / n) as i16,
+                ));
+
+                iprintln!(
+                    &mut itm.stim[0],
+                    "{} samples, average {}, {}, {}",
+                    count,
+                    average.x,
+                    average.y,
+                    average.z,
+                );
+            }
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {}
+}
+
+#[interrupt]
+fn EXTI4() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT1.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
@@ -0,0 +1,158 @@
+//! This example plays LED animations from a timer interrupt while the main
+//! loop only sleeps and handles button events.
+//!
+//! A breathing status pattern runs in the background. A click flashes all
+//! LEDs, a double click plays a custom sequence and a long press toggles a
+//! fast blinking error pattern which overrides everything else.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use board::button::{Button, Event};
+use board::hal::interrupt;
+use board::hal::prelude::*;
+use board::hal::stm32::{self, TIM2};
+use board::hal::timer::{Event as TimerEvent, Timer};
+use board::led::{Animator, Keyframe, Leds, Pattern, Priority};
+use board::Board;
+
+/// Period of the animation tick in ms, the button is ticked every 1 ms
+const ANIMATION_TICK_MS: u16 = 10;
+
+static ANIMATOR: Mutex<RefCell<Option<Animator<Leds>>>> = Mutex::new(RefCell::new(None));
+static BUTTON: Mutex<RefCell<Option<Button>>> = Mutex::new(RefCell::new(None));
+static TIMER: Mutex<RefCell<Option<Timer<TIM2>>>> = Mutex::new(RefCell::new(None));
+
+static SEQUENCE: [Keyframe; 4] = [
+    Keyframe {
+        brightness: [255, 0, 0, 0],
+        duration_ms: 150,
+        fade: false,
+    },
+    Keyframe {
+        brightness: [255, 255, 0, 0],
+        duration_ms: 150,
+        fade: false,
+    },
+    Keyframe {
+        brightness: [255, 255, 255, 0],
+        duration_ms: 150,
+        fade: false,
+    },
+    Keyframe {
+        brightness: [255, 255, 255, 255],
+        duration_ms: 300,
+        fade: false,
+    },
+];
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut animator = Animator::new(board.leds, ANIMATION_TICK_MS);
+        animator.play(
+            Priority::Status,
+            Pattern::Breathe {
+                mask: 0b1111,
+                period_ms: 3000,
+            },
+        );
+
+        let mut timer = Timer::tim2(board.device.tim2, 1.khz(), board.clocks);
+        timer.listen(TimerEvent::TimeOut);
+
+        cortex_m::interrupt::free(|cs| {
+            ANIMATOR.borrow(cs).replace(Some(animator));
+            BUTTON.borrow(cs).replace(Some(board.button));
+            TIMER.borrow(cs).replace(Some(timer));
+        });
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI0);
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::TIM2);
+        }
+
+        loop {
+            cortex_m::interrupt::free(|cs| {
+                let mut button = BUTTON.borrow(cs).borrow_mut();
+                let mut animator = ANIMATOR.borrow(cs).borrow_mut();
+                if let (Some(button), Some(animator)) = (button.as_mut(), animator.as_mut()) {
+                    while let Some(event) = button.poll() {
+                        match event {
+                            Event::Click => animator.play(
+                                Priority::Notification,
+                                Pattern::Flash {
+                                    mask: 0b1111,
+                                    duration_ms: 200,
+                                },
+                            ),
+                            Event::DoubleClick => animator.play(
+                                Priority::Notification,
+                                Pattern::Sequence {
+                                    frames: &SEQUENCE,
+                                    repeat: false,
+                                },
+                            ),
+                            Event::LongPress(_) if animator.is_playing(Priority::Error) => {
+                                animator.stop(Priority::Error)
+                            }
+                            Event::LongPress(_) => animator.play(
+                                Priority::Error,
+                                Pattern::Blink {
+                                    mask: 0b1111,
+                                    period_ms: 200,
+                                },
+                            ),
+                            _ => {}
+                        }
+                    }
+                }
+            });
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {
+        continue;
+    }
+}
+
+#[interrupt]
+fn EXTI0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(button) = BUTTON.borrow(cs).borrow_mut().as_mut() {
+            button.on_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn TIM2() {
+    static mut TICKS: u16 = 0;
+
+    cortex_m::interrupt::free(|cs| {
+        if let Some(timer) = TIMER.borrow(cs).borrow_mut().as_mut() {
+            timer.clear_interrupt(TimerEvent::TimeOut);
+        }
+        if let Some(button) = BUTTON.borrow(cs).borrow_mut().as_mut() {
+            button.tick();
+        }
+
+        *TICKS += 1;
+        if *TICKS == ANIMATION_TICK_MS {
+            *TICKS = 0;
+            if let Some(animator) = ANIMATOR.borrow(cs).borrow_mut().as_mut() {
+                animator.tick();
+            }
+        }
+    });
+}
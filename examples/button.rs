@@ -0,0 +1,102 @@
+//! This example reacts to gestures on the user button: a click moves the lit
+//! LED clockwise, a double click moves it counter-clockwise and a long press
+//! turns all LEDs on.
+//!
+//! The button is debounced from a 1 kHz TIM2 interrupt.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use board::button::{Button, Event};
+use board::hal::interrupt;
+use board::hal::prelude::*;
+use board::hal::stm32::{self, TIM2};
+use board::hal::timer::{Event as TimerEvent, Timer};
+use board::Board;
+
+static BUTTON: Mutex<RefCell<Option<Button>>> = Mutex::new(RefCell::new(None));
+static TIMER: Mutex<RefCell<Option<Timer<TIM2>>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+
+        let mut timer = Timer::tim2(board.device.tim2, 1.khz(), board.clocks);
+        timer.listen(TimerEvent::TimeOut);
+
+        cortex_m::interrupt::free(|cs| {
+            BUTTON.borrow(cs).replace(Some(board.button));
+            TIMER.borrow(cs).replace(Some(timer));
+        });
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI0);
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::TIM2);
+        }
+
+        let mut current = 0;
+        leds[current].on();
+
+        loop {
+            let event = cortex_m::interrupt::free(|cs| {
+                BUTTON
+                    .borrow(cs)
+                    .borrow_mut()
+                    .as_mut()
+                    .and_then(|b| b.poll())
+            });
+
+            let next = match event {
+                Some(Event::Click) => (current + 1) % leds.len(),
+                Some(Event::DoubleClick) => (current + leds.len() - 1) % leds.len(),
+                Some(Event::LongPress(_)) => {
+                    for led in leds.iter_mut() {
+                        led.on();
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            for led in leds.iter_mut() {
+                led.off();
+            }
+            leds[next].on();
+            current = next;
+        }
+    }
+
+    loop {
+        continue;
+    }
+}
+
+#[interrupt]
+fn EXTI0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(button) = BUTTON.borrow(cs).borrow_mut().as_mut() {
+            button.on_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn TIM2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(timer) = TIMER.borrow(cs).borrow_mut().as_mut() {
+            timer.clear_interrupt(TimerEvent::TimeOut);
+        }
+        if let Some(button) = BUTTON.borrow(cs).borrow_mut().as_mut() {
+            button.tick();
+        }
+    });
+}
@@ -0,0 +1,42 @@
+//! This example reads the onboard magnetometer and points the LEDs towards
+//! magnetic north, blending between the two nearest ones
+//!
+//! Additionally, the current heading is printed via itm.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m_rt::entry;
+
+use board::Board;
+
+use cortex_m::iprintln;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds.into_pwm(board.device.tim4, board.clocks);
+        let mut compass = board.compass;
+        let mut itm = board.core.ITM;
+
+        loop {
+            if !compass.data_ready().unwrap() {
+                continue;
+            }
+
+            let heading = compass.heading().unwrap();
+
+            iprintln!(&mut itm.stim[0], "heading: {}", heading);
+
+            // The heading is the bearing of the red (x) edge, the top edge
+            // is 90° counterclockwise from it. North lies at minus the
+            // heading of the top edge, seen from the top edge.
+            leds.point_to(90.0 - heading);
+        }
+    }
+
+    loop {}
+}
@@ -0,0 +1,65 @@
+//! This example calibrates the magnetometer by fitting an ellipsoid to
+//! readings taken while the board is turned around in every direction.
+//!
+//! The LEDs light up one by one as more directions are covered. Once all are
+//! lit, the fit quality and the serialized calibration are printed via itm,
+//! and the LEDs point towards magnetic north using the calibrated readings.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use board::calibration::EllipsoidFit;
+use board::Board;
+
+/// Coverage required before fitting
+const COVERAGE: f32 = 0.9;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+        let mut compass = board.compass;
+        let mut itm = board.core.ITM;
+
+        let mut fit = EllipsoidFit::new();
+        compass.set_calibration(None);
+
+        loop {
+            while fit.coverage() < COVERAGE {
+                if compass.data_ready().unwrap() {
+                    fit.add(compass.mag_gauss().unwrap());
+
+                    let lit = (fit.coverage() / COVERAGE * 4.0) as u8;
+                    leds.set_mask((1 << lit) - 1);
+                }
+            }
+
+            if let Some((calibration, quality)) = fit.solve() {
+                iprintln!(&mut itm.stim[0], "{:?}", quality);
+                iprintln!(&mut itm.stim[0], "{:?}", &calibration.to_bytes()[..]);
+                compass.set_calibration(Some(calibration));
+                break;
+            }
+
+            // Degenerate readings, start over
+            fit = EllipsoidFit::new();
+        }
+
+        loop {
+            if compass.data_ready().unwrap() {
+                // The heading is the bearing of the red (x) edge, north lies
+                // at minus the heading of the top edge
+                let heading = compass.heading().unwrap();
+                leds.point_to(90.0 - heading);
+            }
+        }
+    }
+
+    loop {}
+}
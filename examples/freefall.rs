@@ -0,0 +1,135 @@
+//! This example uses both inertial interrupt generators of the accelerometer.
+//!
+//! Generator 1 detects free fall on INT1 (PE4) and lights the red LED until
+//! the board lies flat again. Generator 2 tracks the orientation on INT2
+//! (PE5) and lights the LED on the edge facing down.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use board::accelerometer::{DataRate, Generator, GeneratorConfig, InterruptPin};
+use board::exti::InterruptLine;
+use board::hal::gpio::gpioe::{PE4, PE5};
+use board::hal::gpio::{Edge, Floating, Input};
+use board::hal::interrupt;
+use board::hal::stm32;
+use board::led::LedColor;
+use board::Board;
+
+static INT1: Mutex<RefCell<Option<InterruptLine<PE4<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+static INT2: Mutex<RefCell<Option<InterruptLine<PE5<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+        let mut accelerometer = board.accelerometer;
+        let mut device = board.device;
+
+        accelerometer.set_data_rate(DataRate::Hz100).unwrap();
+        accelerometer
+            .enable_generator(Generator::One, GeneratorConfig::free_fall(350, 30))
+            .unwrap();
+        accelerometer
+            .enable_generator(
+                Generator::Two,
+                GeneratorConfig {
+                    pin: InterruptPin::Int2,
+                    ..GeneratorConfig::orientation(600)
+                },
+            )
+            .unwrap();
+
+        let int1 = InterruptLine::new(
+            board.mems_interrupts.accel_int1,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        let int2 = InterruptLine::new(
+            board.mems_interrupts.accel_int2,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        cortex_m::interrupt::free(|cs| {
+            INT1.borrow(cs).replace(Some(int1));
+            INT2.borrow(cs).replace(Some(int2));
+        });
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI4);
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI9_5);
+        }
+
+        let mut falling = false;
+
+        loop {
+            if accelerometer
+                .generator_event(Generator::One)
+                .unwrap()
+                .is_some()
+            {
+                falling = true;
+                leds.set_mask(1 << LedColor::Red as u8);
+            }
+
+            if let Some(event) = accelerometer.generator_event(Generator::Two).unwrap() {
+                let axes = event.axes;
+                if axes.z_high {
+                    // Lying flat again after a fall
+                    falling = false;
+                }
+
+                if !falling {
+                    let down = if axes.x_low {
+                        Some(LedColor::Red)
+                    } else if axes.x_high {
+                        Some(LedColor::Green)
+                    } else if axes.y_low {
+                        Some(LedColor::Orange)
+                    } else if axes.y_high {
+                        Some(LedColor::Blue)
+                    } else {
+                        None
+                    };
+                    leds.set_mask(down.map_or(0, |led| 1 << led as u8));
+                }
+            }
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {
+        continue;
+    }
+}
+
+#[interrupt]
+fn EXTI4() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT1.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
+
+#[interrupt]
+fn EXTI9_5() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT2.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
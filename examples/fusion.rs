@@ -0,0 +1,72 @@
+//! This example fuses all three MEMS sensors into the board orientation.
+//!
+//! The Madgwick filter runs at the gyroscope data rate of 190 Hz. The LEDs
+//! point towards magnetic north whatever the tilt of the board, and the Euler
+//! angles and linear acceleration are printed via itm twice per second.
+//! Swap in `Mahony::default()` to compare both filters, or use `poll`
+//! instead of `poll_with_compass` for 6-DoF.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use board::accelerometer::DataRate as AccelDataRate;
+use board::compass::DataRate as MagDataRate;
+use board::fusion::{Ahrs, Madgwick};
+use board::gyroscope::DataRate as GyroDataRate;
+use board::Board;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds.into_pwm(board.device.tim4, board.clocks);
+        let mut gyroscope = board.gyroscope;
+        let mut accelerometer = board.accelerometer;
+        let mut compass = board.compass;
+        let mut itm = board.core.ITM;
+
+        gyroscope.set_data_rate(GyroDataRate::Hz190).unwrap();
+        accelerometer.set_data_rate(AccelDataRate::Hz200).unwrap();
+        compass.set_data_rate(MagDataRate::Hz220).unwrap();
+
+        let mut ahrs = Ahrs::new(Madgwick::default(), gyroscope.sample_rate());
+        let mut samples = 0;
+
+        loop {
+            if !gyroscope.data_ready().unwrap() {
+                continue;
+            }
+
+            ahrs.poll_with_compass(&mut gyroscope, &mut accelerometer, &mut compass)
+                .unwrap();
+
+            // North lies at minus the heading, seen from the top edge
+            leds.point_to(360.0 - ahrs.heading());
+
+            samples += 1;
+            if samples == 95 {
+                samples = 0;
+
+                let euler = ahrs.euler();
+                let linear = ahrs.linear_acceleration();
+                iprintln!(
+                    &mut itm.stim[0],
+                    "roll {}, pitch {}, yaw {}, linear {}, {}, {} g",
+                    euler.roll,
+                    euler.pitch,
+                    euler.yaw,
+                    linear.x,
+                    linear.y,
+                    linear.z,
+                );
+            }
+        }
+    }
+
+    loop {}
+}
@@ -3,39 +3,27 @@
 #![no_std]
 
 extern crate panic_itm;
-use cortex_m::{iprintln, Peripherals};
+use cortex_m::iprintln;
 
 use stm32f411e_disco as board;
 
 use crate::board::{
-    hal::stm32,
     hal::{delay::Delay, prelude::*},
-    led::{LedColor, Leds},
+    led::LedColor,
+    Board,
 };
 
-//use cortex_m::peripheral::Peripherals;
-
 use cortex_m_rt::entry;
 
 #[entry]
 fn main() -> ! {
-    if let (Some(p), Some(cp)) = (stm32::Peripherals::take(), Peripherals::take()) {
-        let gpiod = p.GPIOD.split();
-
-        // Initialize on-board LEDs
-        let mut leds = Leds::new(gpiod);
-
-        // Constrain clock registers
-        let rcc = p.RCC.constrain();
-
-        // Configure clock to 1OO MHz (i.e. the maximum) and freeze it
-        let clocks = rcc.cfgr.sysclk(100.mhz()).freeze();
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
 
         // Get delay provider
-        let mut delay = Delay::new(cp.SYST, clocks);
-
+        let mut delay = Delay::new(board.core.SYST, board.clocks);
 
-        let mut itm = cp.ITM;
+        let mut itm = board.core.ITM;
         let stim = &mut itm.stim[0];
         iprintln!(stim, "Hello, world!");
 
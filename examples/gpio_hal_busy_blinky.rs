@@ -5,21 +5,14 @@ use panic_halt as _;
 
 use stm32f411e_disco as board;
 
-use crate::board::{
-    hal::prelude::*,
-    hal::stm32,
-    led::{LedColor, Leds},
-};
+use crate::board::{led::LedColor, Board};
 
 use cortex_m_rt::entry;
 
 #[entry]
 fn main() -> ! {
-    if let Some(p) = stm32::Peripherals::take() {
-        let gpiod = p.GPIOD.split();
-
-        // Initialize on-board LEDs
-        let mut leds = Leds::new(gpiod);
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
 
         // Endlessly blink the 4 LEDs in a circle, delaying by executing the state write many times
         // in a row
@@ -0,0 +1,78 @@
+//! This example integrates the gyroscope Z rate into a yaw angle while the
+//! zero-rate level is tracked in the background.
+//!
+//! Leave the board at rest for a few seconds first, then turn it flat on the
+//! table. Every second, the yaw angle and the calibration in use are printed
+//! via itm. The serialized calibration can be stored and passed to
+//! `Gyroscope::set_calibration` at the next boot.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use board::calibration::{BiasEstimator, GyroCalibration};
+use board::gyroscope::DataRate;
+use board::Board;
+
+/// Maximum deviation from the window mean, in dps, for the board to count
+/// as still
+const STILL_DPS: f32 = 0.5;
+
+/// Maximum change of the zero-rate level between still windows, in dps,
+/// covering the ±10 dps zero-rate level of an uncalibrated L3GD20
+const MAX_CHANGE_DPS: f32 = 10.0;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut gyroscope = board.gyroscope;
+        let mut itm = board.core.ITM;
+
+        gyroscope.set_data_rate(DataRate::Hz95).unwrap();
+        let rate_hz = gyroscope.sample_rate();
+
+        // Two-second windows
+        let window = (2.0 * rate_hz) as u16;
+        let estimator = BiasEstimator::new(
+            STILL_DPS,
+            window,
+            MAX_CHANGE_DPS,
+            GyroCalibration::default(),
+        );
+        gyroscope.enable_bias_tracking(estimator);
+
+        let mut yaw = 0.0;
+        let mut samples = 0;
+
+        loop {
+            if !gyroscope.data_ready().unwrap() {
+                continue;
+            }
+
+            let rate = gyroscope.gyro_dps().unwrap();
+            yaw += rate.z / rate_hz;
+
+            samples += 1;
+            if samples == 95 {
+                samples = 0;
+
+                let calibration = gyroscope.calibration().unwrap();
+                iprintln!(
+                    &mut itm.stim[0],
+                    "yaw {}, bias {:?}, drift {:?} dps/LSB, {} bytes",
+                    yaw,
+                    calibration.bias,
+                    calibration.temperature_coefficient,
+                    calibration.to_bytes().len(),
+                );
+            }
+        }
+    }
+
+    loop {}
+}
@@ -0,0 +1,112 @@
+//! This example streams gyroscope samples at 760 Hz (840 Hz on the I3G4250D)
+//! through the FIFO.
+//!
+//! The FIFO watermark interrupt on INT2/DRDY (PE1) wakes the CPU. The EXTI1
+//! handler captures the DWT cycle counter, which the main loop then uses to
+//! timestamp the samples read in one burst. The rate of the newest sample and
+//! the time covered by the batch are printed via itm.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::iprintln;
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+
+use board::exti::InterruptLine;
+use board::gyroscope::{DataRate, FifoMode, Interrupts, TimedSample, FIFO_DEPTH};
+use board::hal::gpio::gpioe::PE1;
+use board::hal::gpio::{Edge, Floating, Input};
+use board::hal::interrupt;
+use board::hal::stm32;
+use board::Board;
+
+static INT2: Mutex<RefCell<Option<InterruptLine<PE1<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Cycle count at the last watermark interrupt
+static TIMESTAMP: AtomicU32 = AtomicU32::new(0);
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut gyroscope = board.gyroscope;
+        let mut device = board.device;
+        let mut dcb = board.core.DCB;
+        let mut dwt = board.core.DWT;
+        let mut itm = board.core.ITM;
+        let clock_hz = board.clocks.sysclk().0;
+
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+
+        gyroscope.set_data_rate(DataRate::Hz760).unwrap();
+        gyroscope.set_fifo_mode(FifoMode::Stream, 16).unwrap();
+        gyroscope
+            .route_interrupts(Interrupts {
+                fifo_watermark: true,
+                ..Interrupts::default()
+            })
+            .unwrap();
+
+        let line = InterruptLine::new(
+            board.mems_interrupts.gyro_int2,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        cortex_m::interrupt::free(|cs| INT2.borrow(cs).replace(Some(line)));
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI1);
+        }
+
+        let mut samples = [TimedSample::default(); FIFO_DEPTH];
+
+        loop {
+            // INT2 stays high until the FIFO is drained below the watermark,
+            // so read everything that is pending after every wake-up
+            let timestamp = TIMESTAMP.load(Ordering::Relaxed);
+            let count = gyroscope
+                .read_fifo_timed(timestamp, clock_hz, &mut samples)
+                .unwrap();
+            if count > 0 {
+                let newest = samples[count - 1];
+                let span = newest.timestamp.wrapping_sub(samples[0].timestamp);
+                let rate = gyroscope.to_dps(newest.rate);
+
+                iprintln!(
+                    &mut itm.stim[0],
+                    "{} samples over {} us, newest {}, {}, {} dps",
+                    count,
+                    span / (clock_hz / 1_000_000),
+                    rate.x,
+                    rate.y,
+                    rate.z,
+                );
+            }
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {}
+}
+
+#[interrupt]
+fn EXTI1() {
+    TIMESTAMP.store(DWT::cycle_count(), Ordering::Relaxed);
+
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT2.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
@@ -0,0 +1,84 @@
+//! This example sleeps until the board is rotated.
+//!
+//! The gyroscope raises INT1 (PE0) once the rate about any axis exceeds
+//! 90 dps. After each wake-up the LEDs show the axes that triggered it: red
+//! for X, orange for Y and blue for Z. PE0 takes EXTI0 over from the user
+//! button.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use board::exti::InterruptLine;
+use board::gyroscope::RateInterruptConfig;
+use board::hal::gpio::gpioe::PE0;
+use board::hal::gpio::{Edge, Floating, Input};
+use board::hal::interrupt;
+use board::hal::stm32;
+use board::led::LedColor;
+use board::Board;
+
+static INT1: Mutex<RefCell<Option<InterruptLine<PE0<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut gyroscope = board.gyroscope;
+        let mut leds = board.leds;
+        let mut device = board.device;
+
+        gyroscope
+            .enable_rate_interrupt(RateInterruptConfig::wake_up(90.0))
+            .unwrap();
+
+        let line = InterruptLine::new(
+            board.mems_interrupts.gyro_int1,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        cortex_m::interrupt::free(|cs| INT1.borrow(cs).replace(Some(line)));
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI0);
+        }
+
+        loop {
+            // Reading the event releases the latched INT1
+            if let Some(event) = gyroscope.rate_event().unwrap() {
+                let mut mask = 0;
+                if event.x {
+                    mask |= 1 << LedColor::Red as u8;
+                }
+                if event.y {
+                    mask |= 1 << LedColor::Orange as u8;
+                }
+                if event.z {
+                    mask |= 1 << LedColor::Blue as u8;
+                }
+                leds.set_mask(mask);
+            }
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {}
+}
+
+#[interrupt]
+fn EXTI0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT1.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
@@ -11,7 +11,7 @@ use stm32f411e_disco as board;
 
 use ssd1306::{displayrotation::DisplayRotation, mode::TerminalMode, Builder};
 
-use crate::board::{hal::i2c::*, hal::prelude::*, hal::stm32};
+use crate::board::{clocks::Preset, hal::i2c::*, hal::prelude::*, hal::stm32};
 
 use core::fmt::Write;
 
@@ -19,10 +19,9 @@ use core::fmt::Write;
 fn main() -> ! {
     if let Some(p) = stm32::Peripherals::take() {
         let gpiob = p.GPIOB.split();
-        let rcc = p.RCC.constrain();
 
-        // Set up the clocks, going too fast exhibits some problem so let's take it slow for now
-        let clocks = rcc.cfgr.sysclk(40.mhz()).freeze();
+        // Run at 100 MHz from the 8 MHz HSE
+        let (clocks, _) = Preset::Performance.freeze(p.RCC);
 
         // Set up the SCL pin of the I2C bus at PB6
         let scl = gpiob
@@ -0,0 +1,53 @@
+//! This example prints pitch, roll and tilt of the board via itm and reports
+//! orientation changes, lighting the LED on the edge facing up.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use board::inclinometer::{Config, Inclinometer, Orientation};
+use board::led::LedColor;
+use board::Board;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+        let mut accelerometer = board.accelerometer;
+        let mut itm = board.core.ITM;
+
+        let mut inclinometer = Inclinometer::new(Config::default());
+
+        loop {
+            if let Some(orientation) = inclinometer.poll(&mut accelerometer).unwrap() {
+                iprintln!(&mut itm.stim[0], "orientation: {:?}", orientation);
+
+                let mask = match orientation {
+                    Orientation::FaceUp => 0b1111,
+                    Orientation::FaceDown => 0,
+                    Orientation::Portrait => 1 << LedColor::Orange as u8,
+                    Orientation::PortraitUpsideDown => 1 << LedColor::Blue as u8,
+                    Orientation::LandscapeLeft => 1 << LedColor::Red as u8,
+                    Orientation::LandscapeRight => 1 << LedColor::Green as u8,
+                };
+                leds.set_mask(mask);
+            }
+
+            let angles = inclinometer.angles();
+            iprintln!(
+                &mut itm.stim[0],
+                "pitch {}, roll {}, tilt {}",
+                angles.pitch,
+                angles.roll,
+                angles.tilt,
+            );
+        }
+    }
+
+    loop {}
+}
@@ -13,21 +13,15 @@ extern crate embedded_hal as hal;
 extern crate panic_itm;
 extern crate stm32f411e_disco as board;
 
-use board::hal::prelude::*;
-use board::hal::stm32;
+use board::Board;
 
-use cortex_m::{iprintln, Peripherals};
+use cortex_m::iprintln;
 use cortex_m_rt::entry;
 
 #[entry]
 fn main() -> ! {
-    if let (Some(p), Some(cp)) = (stm32::Peripherals::take(), Peripherals::take()) {
-        // Constrain clock registers
-        let rcc = p.RCC.constrain();
-        // Configure clock to 100 MHz (i.e. the maximum) and freeze it
-        rcc.cfgr.sysclk(100.mhz()).freeze();
-
-        let mut itm = cp.ITM;
+    if let Ok(board) = Board::take() {
+        let mut itm = board.core.ITM;
         let stim = &mut itm.stim[0];
 
         iprintln!(stim, "Hello, world!");
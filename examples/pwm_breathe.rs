@@ -0,0 +1,36 @@
+//! This example fades the LEDs in and out with TIM4 PWM, one after the other,
+//! using gamma-corrected brightness for a smooth breathing effect.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use crate::board::{
+    hal::{delay::Delay, prelude::*},
+    Board,
+};
+
+use cortex_m_rt::entry;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds.into_pwm(board.device.tim4, board.clocks);
+        let mut delay = Delay::new(board.core.SYST, board.clocks);
+
+        loop {
+            for led in leds.iter_mut() {
+                for brightness in (0..=255u8).chain((0..=255u8).rev()) {
+                    led.set_brightness(brightness);
+                    delay.delay_ms(2_u16);
+                }
+            }
+        }
+    }
+
+    loop {
+        continue;
+    }
+}
@@ -0,0 +1,31 @@
+//! This example identifies the MEMS sensors fitted on the board and prints
+//! them, together with the board revision, via itm.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m_rt::entry;
+
+use board::Board;
+
+use cortex_m::iprintln;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut itm = board.core.ITM;
+
+        iprintln!(
+            &mut itm.stim[0],
+            "{:?}: {:?}, {:?}",
+            board.sensors.revision(),
+            board.sensors.ecompass,
+            board.sensors.gyroscope,
+        );
+    }
+
+    loop {}
+}
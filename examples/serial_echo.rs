@@ -14,28 +14,26 @@ use nb::block;
 
 use crate::board::{
     hal::prelude::*,
-    hal::stm32,
     serial::{config::Config, Serial},
+    Board,
 };
 
 #[cortex_m_rt::entry]
 fn main() -> ! {
-    if let Some(p) = stm32::Peripherals::take() {
-        let gpioa = p.GPIOA.split();
-        let rcc = p.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.mhz()).freeze();
+    if let Ok(board) = Board::take() {
+        let pins = board.pins;
 
         // USART2 at PA2 (TX) and PA3(RX) are connected to ST-Link
         // (well, not really, you're supposed to wire them yourself!)
-        let tx = gpioa.pa2.into_alternate_af7();
-        let rx = gpioa.pa3.into_alternate_af7();
+        let tx = pins.pa2.into_alternate_af7();
+        let rx = pins.pa3.into_alternate_af7();
 
         // Set up USART 2 configured pins and a baudrate of 115200 baud
         let serial = Serial::usart2(
-            p.USART2,
+            board.device.usart2,
             (tx, rx),
             Config::default().baudrate(115_200.bps()),
-            clocks,
+            board.clocks,
         )
         .unwrap();
 
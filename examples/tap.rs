@@ -0,0 +1,93 @@
+//! This example detects taps on the board with the accelerometer click
+//! engine.
+//!
+//! A single tap on the top side moves the lit LED clockwise, a double tap
+//! moves it counter-clockwise. Taps are signalled on INT1 (PE4), the CPU
+//! sleeps in between.
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f411e_disco as board;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use board::accelerometer::{Axes, DataRate, TapConfig};
+use board::exti::InterruptLine;
+use board::hal::gpio::gpioe::PE4;
+use board::hal::gpio::{Edge, Floating, Input};
+use board::hal::interrupt;
+use board::hal::stm32;
+use board::Board;
+
+static INT1: Mutex<RefCell<Option<InterruptLine<PE4<Input<Floating>>>>>> =
+    Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds;
+        let mut accelerometer = board.accelerometer;
+        let mut device = board.device;
+
+        let z = Axes {
+            x: false,
+            y: false,
+            z: true,
+        };
+
+        accelerometer.set_data_rate(DataRate::Hz400).unwrap();
+        accelerometer
+            .enable_tap_detection(TapConfig {
+                single: z,
+                double: z,
+                ..TapConfig::default()
+            })
+            .unwrap();
+
+        let line = InterruptLine::new(
+            board.mems_interrupts.accel_int1,
+            &mut device.syscfg,
+            &mut device.exti,
+            Edge::RISING,
+        );
+        cortex_m::interrupt::free(|cs| INT1.borrow(cs).replace(Some(line)));
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(stm32::Interrupt::EXTI4);
+        }
+
+        let mut current = 0;
+        leds[current].on();
+
+        loop {
+            if let Some(tap) = accelerometer.tap_event().unwrap() {
+                current = if tap.double {
+                    (current + leds.len() - 1) % leds.len()
+                } else {
+                    (current + 1) % leds.len()
+                };
+                leds.set_mask(1 << current);
+            }
+
+            cortex_m::asm::wfi();
+        }
+    }
+
+    loop {
+        continue;
+    }
+}
+
+#[interrupt]
+fn EXTI4() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(line) = INT1.borrow(cs).borrow_mut().as_mut() {
+            line.clear();
+        }
+    });
+}
@@ -0,0 +1,46 @@
+//! This example shows the tilt-compensated heading: the LEDs point towards
+//! true north even while the board is tilted, and the heading is printed via
+//! itm.
+#![no_main]
+#![no_std]
+
+use panic_itm as _;
+
+use stm32f411e_disco as board;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use board::heading::TiltCompensatedCompass;
+use board::Board;
+
+/// Magnetic declination at the place of use, east positive
+const DECLINATION: f32 = 1.2;
+
+#[entry]
+fn main() -> ! {
+    if let Ok(board) = Board::take() {
+        let mut leds = board.leds.into_pwm(board.device.tim4, board.clocks);
+        let mut itm = board.core.ITM;
+
+        let mut compass = TiltCompensatedCompass::new(board.accelerometer, board.compass);
+        compass.set_declination(DECLINATION);
+        compass.set_smoothing(0.2);
+
+        loop {
+            if let Some(heading) = compass.read().unwrap() {
+                iprintln!(
+                    &mut itm.stim[0],
+                    "magnetic {}, true {}",
+                    heading.magnetic,
+                    heading.true_north,
+                );
+
+                // North lies at minus the heading, seen from the top edge
+                leds.point_to(360.0 - heading.true_north);
+            }
+        }
+    }
+
+    loop {}
+}
@@ -1,81 +1,1109 @@
-use accelerometer;
-use lis302dl;
+//! LSM303DLHC / LSM303AGR accelerometer on I2C1
 
-use crate::hal::gpio;
-use crate::hal::gpio::gpioa;
-use crate::hal::gpio::gpioe;
-use crate::hal::prelude::*;
+use core::fmt::Debug;
+
+use accelerometer::vector::{F32x3, I16x3};
+
+use crate::bus::{self, I2c1};
+use crate::calibration::AccelCalibration;
+use crate::hal::gpio::{self, gpiob};
+use crate::hal::i2c;
 use crate::hal::rcc;
-use crate::hal::spi;
 use crate::hal::stm32;
+use crate::revision::{self, EcompassVariant};
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// I2C address of the accelerometer die
+const ADDRESS: u8 = 0x19;
+
+/// Sub-address bit enabling register auto-increment on multi-byte reads
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// Number of samples the FIFO holds
+pub const FIFO_DEPTH: usize = 32;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+enum Register {
+    // LSM303AGR only
+    OUT_TEMP_L_A = 0x0C,
+    TEMP_CFG_REG_A = 0x1F,
+    // Both parts
+    CTRL_REG1_A = 0x20,
+    CTRL_REG3_A = 0x22,
+    CTRL_REG4_A = 0x23,
+    CTRL_REG5_A = 0x24,
+    CTRL_REG6_A = 0x25,
+    OUT_X_L_A = 0x28,
+    FIFO_CTRL_REG_A = 0x2E,
+    FIFO_SRC_REG_A = 0x2F,
+    INT1_CFG_A = 0x30,
+    INT1_SRC_A = 0x31,
+    INT1_THS_A = 0x32,
+    INT1_DURATION_A = 0x33,
+    INT2_CFG_A = 0x34,
+    INT2_SRC_A = 0x35,
+    INT2_THS_A = 0x36,
+    INT2_DURATION_A = 0x37,
+    CLICK_CFG_A = 0x38,
+    CLICK_SRC_A = 0x39,
+    CLICK_THS_A = 0x3A,
+    TIME_LIMIT_A = 0x3B,
+    TIME_LATENCY_A = 0x3C,
+    TIME_WINDOW_A = 0x3D,
+}
+
+/// Resolution and power mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// 8-bit samples, lowest current consumption
+    LowPower,
+    /// 10-bit samples
+    Normal,
+    /// 12-bit samples
+    HighResolution,
+}
+
+impl Mode {
+    /// Right shift turning the left-justified output into a sample
+    fn shift(self) -> u8 {
+        match self {
+            Mode::LowPower => 8,
+            Mode::Normal => 6,
+            Mode::HighResolution => 4,
+        }
+    }
+}
+
+/// Accelerometer full scale range
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FullScale {
+    /// ±2 g
+    G2 = 0b00,
+    /// ±4 g
+    G4 = 0b01,
+    /// ±8 g
+    G8 = 0b10,
+    /// ±16 g
+    G16 = 0b11,
+}
+
+impl FullScale {
+    /// Full scale in g
+    fn g(self) -> u16 {
+        match self {
+            FullScale::G2 => 2,
+            FullScale::G4 => 4,
+            FullScale::G8 => 8,
+            FullScale::G16 => 16,
+        }
+    }
+
+    /// Sensitivity in high-resolution mode in g/LSB
+    fn sensitivity(self, variant: EcompassVariant) -> f32 {
+        match variant {
+            EcompassVariant::Lsm303dlhc => match self {
+                FullScale::G2 => 0.001,
+                FullScale::G4 => 0.002,
+                FullScale::G8 => 0.004,
+                FullScale::G16 => 0.012,
+            },
+            EcompassVariant::Lsm303agr => match self {
+                FullScale::G2 => 0.000_98,
+                FullScale::G4 => 0.001_95,
+                FullScale::G8 => 0.0039,
+                FullScale::G16 => 0.011_72,
+            },
+        }
+    }
+
+    /// Weight of 1 LSB of the click and inertial interrupt thresholds, in mg
+    fn generator_lsb_mg(self, variant: EcompassVariant) -> f32 {
+        match variant {
+            // Full scale / 128
+            EcompassVariant::Lsm303dlhc => f32::from(self.g()) * 1000.0 / 128.0,
+            EcompassVariant::Lsm303agr => match self {
+                FullScale::G2 => 16.0,
+                FullScale::G4 => 32.0,
+                FullScale::G8 => 62.0,
+                FullScale::G16 => 186.0,
+            },
+        }
+    }
+}
+
+/// Accelerometer output data rate
+///
+/// The 1.62 kHz and 5.376 kHz rates are only available in [`Mode::LowPower`]
+/// and the 1.344 kHz rate only outside of it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DataRate {
+    /// 1 Hz
+    Hz1,
+    /// 10 Hz
+    Hz10,
+    /// 25 Hz
+    Hz25,
+    /// 50 Hz
+    Hz50,
+    /// 100 Hz
+    Hz100,
+    /// 200 Hz
+    Hz200,
+    /// 400 Hz
+    Hz400,
+    /// 1.344 kHz, normal and high-resolution modes
+    Khz1_344,
+    /// 1.62 kHz, low-power mode
+    Khz1_62,
+    /// 5.376 kHz, low-power mode
+    Khz5_376,
+}
+
+impl DataRate {
+    /// Output data rate in Hz
+    pub fn hz(self) -> f32 {
+        match self {
+            DataRate::Hz1 => 1.0,
+            DataRate::Hz10 => 10.0,
+            DataRate::Hz25 => 25.0,
+            DataRate::Hz50 => 50.0,
+            DataRate::Hz100 => 100.0,
+            DataRate::Hz200 => 200.0,
+            DataRate::Hz400 => 400.0,
+            DataRate::Khz1_344 => 1344.0,
+            DataRate::Khz1_62 => 1620.0,
+            DataRate::Khz5_376 => 5376.0,
+        }
+    }
+
+    /// ODR field of CTRL_REG1_A
+    fn odr(self) -> u8 {
+        match self {
+            DataRate::Hz1 => 0b0001,
+            DataRate::Hz10 => 0b0010,
+            DataRate::Hz25 => 0b0011,
+            DataRate::Hz50 => 0b0100,
+            DataRate::Hz100 => 0b0101,
+            DataRate::Hz200 => 0b0110,
+            DataRate::Hz400 => 0b0111,
+            DataRate::Khz1_62 => 0b1000,
+            DataRate::Khz1_344 | DataRate::Khz5_376 => 0b1001,
+        }
+    }
+
+    /// Closest rate supported in `mode`
+    fn supported_in(self, mode: Mode) -> Self {
+        match (self, mode) {
+            (DataRate::Khz1_344, Mode::LowPower) => DataRate::Khz1_62,
+            (DataRate::Khz1_62, _) | (DataRate::Khz5_376, _) if mode != Mode::LowPower => {
+                DataRate::Khz1_344
+            }
+            (data_rate, _) => data_rate,
+        }
+    }
+}
+
+/// Enabled measurement axes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Axes {
+    /// X axis
+    pub x: bool,
+    /// Y axis
+    pub y: bool,
+    /// Z axis
+    pub z: bool,
+}
+
+impl Default for Axes {
+    fn default() -> Self {
+        Axes {
+            x: true,
+            y: true,
+            z: true,
+        }
+    }
+}
+
+/// Measurement axis
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Axis {
+    /// X axis, towards the red LED
+    X,
+    /// Y axis, towards the orange LED
+    Y,
+    /// Z axis, out of the top side of the board
+    Z,
+}
+
+/// Click (tap) detection settings
+///
+/// Durations are converted to output data rate periods when the
+/// configuration is written, so set the data rate first. The threshold is
+/// converted the same way using the full scale range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TapConfig {
+    /// Axes on which single taps are detected
+    pub single: Axes,
+    /// Axes on which double taps are detected
+    pub double: Axes,
+    /// Acceleration peak a tap must exceed, in mg
+    pub threshold_mg: u16,
+    /// Maximum time the acceleration may stay above the threshold, in ms
+    pub time_limit_ms: u16,
+    /// Dead time after the first tap of a double tap, in ms
+    pub latency_ms: u16,
+    /// Time after the latency during which the second tap must start, in ms
+    pub window_ms: u16,
+    /// Interrupt output signalling taps
+    pub pin: InterruptPin,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        TapConfig {
+            single: Axes::default(),
+            double: Axes {
+                x: false,
+                y: false,
+                z: false,
+            },
+            threshold_mg: 750,
+            time_limit_ms: 30,
+            latency_ms: 50,
+            window_ms: 200,
+            pin: InterruptPin::Int1,
+        }
+    }
+}
+
+/// A detected tap
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TapEvent {
+    /// `true` for a double tap
+    pub double: bool,
+    /// Axis the tap was detected on
+    pub axis: Axis,
+    /// `true` if the tap accelerated the board towards the negative axis
+    pub negative: bool,
+}
+
+/// Inertial interrupt generator
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Generator {
+    /// Generator 1 (INT1_CFG_A)
+    One,
+    /// Generator 2 (INT2_CFG_A)
+    Two,
+}
+
+/// Threshold events of each axis
+///
+/// An axis is low when its absolute acceleration is below the threshold and
+/// high when it is above.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AxisEvents {
+    /// X axis below the threshold
+    pub x_low: bool,
+    /// X axis above the threshold
+    pub x_high: bool,
+    /// Y axis below the threshold
+    pub y_low: bool,
+    /// Y axis above the threshold
+    pub y_high: bool,
+    /// Z axis below the threshold
+    pub z_low: bool,
+    /// Z axis above the threshold
+    pub z_high: bool,
+}
+
+impl AxisEvents {
+    /// Low events on all axes
+    pub fn low() -> Self {
+        AxisEvents {
+            x_low: true,
+            y_low: true,
+            z_low: true,
+            ..AxisEvents::default()
+        }
+    }
+
+    /// High events on all axes
+    pub fn high() -> Self {
+        AxisEvents {
+            x_high: true,
+            y_high: true,
+            z_high: true,
+            ..AxisEvents::default()
+        }
+    }
 
-use embedded_hal;
-use embedded_hal::digital::v2::OutputPin;
+    fn bits(self) -> u8 {
+        ((self.z_high as u8) << 5)
+            | ((self.z_low as u8) << 4)
+            | ((self.y_high as u8) << 3)
+            | ((self.y_low as u8) << 2)
+            | ((self.x_high as u8) << 1)
+            | (self.x_low as u8)
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        AxisEvents {
+            x_low: bits & 0b00_0001 != 0,
+            x_high: bits & 0b00_0010 != 0,
+            y_low: bits & 0b00_0100 != 0,
+            y_high: bits & 0b00_1000 != 0,
+            z_low: bits & 0b01_0000 != 0,
+            z_high: bits & 0b10_0000 != 0,
+        }
+    }
+}
+
+/// How an inertial interrupt generator combines the axis events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GeneratorMode {
+    /// Fires when any of the events occurs
+    Or(AxisEvents),
+    /// Fires when all of the events occur at the same time
+    And(AxisEvents),
+    /// Fires when the board moves into another orientation
+    ///
+    /// With `four_d` set, the Z axis is ignored.
+    Movement { four_d: bool },
+    /// Fires while the board stays in a known orientation
+    ///
+    /// With `four_d` set, the Z axis is ignored.
+    Position { four_d: bool },
+}
+
+/// Inertial interrupt generator settings
+///
+/// Like [`TapConfig`], the threshold and duration are converted using the
+/// full scale range and data rate configured at the time they are written.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeneratorConfig {
+    /// Event combination
+    pub mode: GeneratorMode,
+    /// Acceleration threshold, in mg
+    pub threshold_mg: u16,
+    /// Minimum time the condition must hold, in ms
+    pub duration_ms: u16,
+    /// Keep the interrupt output active until the event is read
+    pub latch: bool,
+    /// Interrupt output signalling the events
+    pub pin: InterruptPin,
+}
+
+impl GeneratorConfig {
+    /// Free-fall detection: all axes below `threshold_mg` for `duration_ms`
+    pub fn free_fall(threshold_mg: u16, duration_ms: u16) -> Self {
+        GeneratorConfig {
+            mode: GeneratorMode::And(AxisEvents::low()),
+            threshold_mg,
+            duration_ms,
+            latch: true,
+            pin: InterruptPin::Int1,
+        }
+    }
 
-type Spi1 = spi::Spi<
-    stm32::SPI1,
-    (
-        gpioa::PA5<gpio::Alternate<gpio::AF5>>,
-        gpioa::PA6<gpio::Alternate<gpio::AF5>>,
-        gpioa::PA7<gpio::Alternate<gpio::AF5>>,
-    ),
->;
+    /// Wake-up on motion: any axis above `threshold_mg` for `duration_ms`
+    pub fn wake_up(threshold_mg: u16, duration_ms: u16) -> Self {
+        GeneratorConfig {
+            mode: GeneratorMode::Or(AxisEvents::high()),
+            threshold_mg,
+            duration_ms,
+            latch: true,
+            pin: InterruptPin::Int1,
+        }
+    }
+
+    /// 6D orientation changes, an axis counts as pointing up or down once
+    /// gravity on it exceeds `threshold_mg`
+    pub fn orientation(threshold_mg: u16) -> Self {
+        GeneratorConfig {
+            mode: GeneratorMode::Movement { four_d: false },
+            threshold_mg,
+            duration_ms: 0,
+            latch: false,
+            pin: InterruptPin::Int1,
+        }
+    }
+}
+
+/// An inertial interrupt generator event
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeneratorEvent {
+    /// Generator that fired
+    pub generator: Generator,
+    /// Axis events active at that time
+    ///
+    /// In the 6D modes, the single flag set tells which axis points up
+    /// (`_high`) or down (`_low`).
+    pub axes: AxisEvents,
+}
+
+/// Accelerometer interrupt output
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InterruptPin {
+    /// INT1, wired to PE4
+    Int1,
+    /// INT2, wired to PE5
+    Int2,
+}
+
+/// FIFO operating mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FifoMode {
+    /// FIFO disabled, only the latest sample is kept
+    Bypass,
+    /// Collects samples until full, then stops
+    Fifo,
+    /// Collects samples, discarding the oldest one when full
+    Stream,
+    /// Works as `Stream` until the interrupt generator routed to the given
+    /// pin fires, then as `Fifo`
+    StreamToFifo(InterruptPin),
+}
+
+/// FIFO fill level and flags
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FifoStatus {
+    /// Number of unread samples, up to [`FIFO_DEPTH`]
+    pub len: usize,
+    /// The fill level is above the watermark
+    pub watermark: bool,
+    /// The FIFO is full and, in stream mode, samples have been overwritten
+    pub overrun: bool,
+}
 
-type ChipSelect = gpioe::PE3<gpio::Output<gpio::PushPull>>;
+/// Interrupt sources routed to an interrupt output
+///
+/// INT2 only supports `click`, `generator1` and `generator2`, the other
+/// sources are ignored for that pin.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Interrupts {
+    /// Click detection
+    pub click: bool,
+    /// Inertial interrupt generator 1
+    pub generator1: bool,
+    /// Inertial interrupt generator 2
+    pub generator2: bool,
+    /// New sample available
+    pub data_ready: bool,
+    /// FIFO watermark reached
+    pub fifo_watermark: bool,
+    /// FIFO overrun
+    pub fifo_overrun: bool,
+}
 
-pub struct Accelerometer {
-    lis302dl: lis302dl::Lis302Dl<Spi1, ChipSelect>,
+/// On-board accelerometer
+pub struct Accelerometer<I2C = I2c1> {
+    i2c: I2C,
+    variant: EcompassVariant,
+    mode: Mode,
+    full_scale: FullScale,
+    data_rate: DataRate,
+    axes: Axes,
+    powered_down: bool,
+    calibration: Option<AccelCalibration>,
 }
 
-impl Accelerometer {
+impl Accelerometer<I2c1> {
+    /// Configures I2C1 on PB6/PB9, detects the e-compass variant and
+    /// initializes the accelerometer
     pub fn new(
-        gpioa: gpioa::Parts,
-        gpioe: gpioe::Parts,
-        spi1: stm32::SPI1,
+        scl: gpiob::PB6<gpio::Input<gpio::Floating>>,
+        sda: gpiob::PB9<gpio::Input<gpio::Floating>>,
+        i2c1: stm32::I2C1,
         clocks: rcc::Clocks,
-    ) -> Self {
-        let sck = gpioa.pa5.into_alternate_af5().internal_pull_up(false);
-        let miso = gpioa.pa6.into_alternate_af5().internal_pull_up(false);
-        let mosi = gpioa.pa7.into_alternate_af5().internal_pull_up(false);
-
-        let spi_mode = spi::Mode {
-            polarity: spi::Polarity::IdleLow,
-            phase: spi::Phase::CaptureOnFirstTransition,
+    ) -> Result<Self, revision::Error<i2c::Error>> {
+        let mut i2c = bus::i2c1(scl, sda, i2c1, clocks);
+        let variant = revision::probe_ecompass(&mut i2c)?;
+
+        Ok(Self::from_i2c(i2c, variant)?)
+    }
+}
+
+impl<I2C, E> Accelerometer<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+{
+    /// Initializes the accelerometer on an already configured I2C bus
+    ///
+    /// The sensor is set to 100 Hz, ±8 g, high-resolution (12 bit) mode with
+    /// block data update enabled.
+    pub fn from_i2c(i2c: I2C, variant: EcompassVariant) -> Result<Self, E> {
+        let mut accelerometer = Self {
+            i2c,
+            variant,
+            mode: Mode::HighResolution,
+            full_scale: FullScale::G8,
+            data_rate: DataRate::Hz100,
+            axes: Axes::default(),
+            powered_down: false,
+            calibration: None,
         };
 
-        let spi = spi::Spi::spi1(spi1, (sck, miso, mosi), spi_mode, 10.mhz().into(), clocks);
+        accelerometer.write_ctrl_reg1()?;
+        // BDU, little endian, ±8 g, high resolution
+        accelerometer.write_register(Register::CTRL_REG4_A, 0b1010_1000)?;
+
+        if variant == EcompassVariant::Lsm303agr {
+            // Temperature sensor on
+            accelerometer.write_register(Register::TEMP_CFG_REG_A, 0b1100_0000)?;
+        }
 
-        let mut chip_select = gpioe.pe3.into_push_pull_output();
-        chip_select.set_high().ok();
+        Ok(accelerometer)
+    }
 
-        let config = lis302dl::Config {
-            scale: lis302dl::Scale::PlusMinus8G,
-            ..Default::default()
+    /// Releases the underlying I2C bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Returns the e-compass part this driver talks to
+    pub fn variant(&self) -> EcompassVariant {
+        self.variant
+    }
+
+    /// Sets the resolution and power mode
+    ///
+    /// If the configured data rate is not available in the new mode, the
+    /// closest one is selected, see [`DataRate`].
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), E> {
+        let hr = if mode == Mode::HighResolution {
+            0b1000
+        } else {
+            0
         };
-        let lis302dl = lis302dl::Lis302Dl::new(spi, chip_select, config);
 
-        Self { lis302dl }
+        self.mode = mode;
+        self.data_rate = self.data_rate.supported_in(mode);
+
+        // Clear HR before setting LPen, both set at once is not allowed
+        self.modify_register(Register::CTRL_REG4_A, |r| r & !0b1000)?;
+        self.write_ctrl_reg1()?;
+        self.modify_register(Register::CTRL_REG4_A, |r| r | hr)
+    }
+
+    /// Returns the configured resolution and power mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Sets the full scale range
+    pub fn set_full_scale(&mut self, full_scale: FullScale) -> Result<(), E> {
+        self.modify_register(Register::CTRL_REG4_A, |r| {
+            (r & !0b0011_0000) | ((full_scale as u8) << 4)
+        })?;
+        self.full_scale = full_scale;
+        Ok(())
+    }
+
+    /// Returns the configured full scale range
+    pub fn full_scale(&self) -> FullScale {
+        self.full_scale
+    }
+
+    /// Sets the output data rate
+    ///
+    /// Selecting one of the low-power only rates switches to
+    /// [`Mode::LowPower`], selecting 1.344 kHz in low-power mode switches to
+    /// [`Mode::Normal`]. A powered down sensor stays down and uses the new
+    /// rate once powered up.
+    pub fn set_data_rate(&mut self, data_rate: DataRate) -> Result<(), E> {
+        let mode = match data_rate {
+            DataRate::Khz1_62 | DataRate::Khz5_376 => Mode::LowPower,
+            DataRate::Khz1_344 if self.mode == Mode::LowPower => Mode::Normal,
+            _ => self.mode,
+        };
+
+        self.data_rate = data_rate;
+        if mode != self.mode {
+            self.set_mode(mode)
+        } else {
+            self.write_ctrl_reg1()
+        }
+    }
+
+    /// Returns the configured output data rate
+    pub fn data_rate(&self) -> DataRate {
+        self.data_rate
     }
-}
 
-impl accelerometer::RawAccelerometer<accelerometer::vector::I8x3> for Accelerometer {
-    type Error = spi::Error;
-    fn accel_raw(
+    /// Enables or disables the measurement axes
+    ///
+    /// Disabled axes read as zero.
+    pub fn set_axes(&mut self, axes: Axes) -> Result<(), E> {
+        self.axes = axes;
+        self.write_ctrl_reg1()
+    }
+
+    /// Returns the enabled measurement axes
+    pub fn axes(&self) -> Axes {
+        self.axes
+    }
+
+    /// Stops sampling, keeping the configuration
+    pub fn power_down(&mut self) -> Result<(), E> {
+        self.powered_down = true;
+        self.write_ctrl_reg1()
+    }
+
+    /// Resumes sampling at the configured data rate
+    pub fn power_up(&mut self) -> Result<(), E> {
+        self.powered_down = false;
+        self.write_ctrl_reg1()
+    }
+
+    /// Returns `true` while the sensor is powered down
+    pub fn is_powered_down(&self) -> bool {
+        self.powered_down
+    }
+
+    /// Reads the die temperature in °C
+    ///
+    /// Only the LSM303AGR has a temperature sensor on the accelerometer die,
+    /// `None` is returned on the LSM303DLHC. The sensor is factory trimmed
+    /// for offset only, so expect a few degrees of absolute error.
+    pub fn temperature(&mut self) -> Result<Option<f32>, E> {
+        if self.variant != EcompassVariant::Lsm303agr {
+            return Ok(None);
+        }
+
+        let mut buffer = [0u8; 2];
+        self.i2c.write_read(
+            ADDRESS,
+            &[Register::OUT_TEMP_L_A as u8 | AUTO_INCREMENT],
+            &mut buffer,
+        )?;
+
+        // Left-justified, 1 °C per 256 LSB around 25 °C
+        let raw = i16::from_le_bytes(buffer);
+        Ok(Some(25.0 + raw as f32 / 256.0))
+    }
+
+    /// Routes interrupt sources to one of the interrupt outputs
+    ///
+    /// Replaces any previous routing of that pin.
+    pub fn route_interrupts(&mut self, pin: InterruptPin, interrupts: Interrupts) -> Result<(), E> {
+        match pin {
+            InterruptPin::Int1 => {
+                let value = ((interrupts.click as u8) << 7)
+                    | ((interrupts.generator1 as u8) << 6)
+                    | ((interrupts.generator2 as u8) << 5)
+                    | ((interrupts.data_ready as u8) << 4)
+                    | ((interrupts.fifo_watermark as u8) << 2)
+                    | ((interrupts.fifo_overrun as u8) << 1);
+                self.write_register(Register::CTRL_REG3_A, value)
+            }
+            InterruptPin::Int2 => {
+                let value = ((interrupts.click as u8) << 7)
+                    | ((interrupts.generator1 as u8) << 6)
+                    | ((interrupts.generator2 as u8) << 5);
+                self.modify_register(Register::CTRL_REG6_A, |r| (r & !0b1110_0000) | value)
+            }
+        }
+    }
+
+    /// Selects the polarity of both interrupt outputs
+    pub fn set_interrupts_active_low(&mut self, active_low: bool) -> Result<(), E> {
+        self.modify_register(Register::CTRL_REG6_A, |r| {
+            (r & !0b0000_0010) | ((active_low as u8) << 1)
+        })
+    }
+
+    /// Configures the FIFO mode and watermark level
+    ///
+    /// The watermark flag is raised once more than `watermark` samples are
+    /// pending, `watermark` is clamped to 31. Route
+    /// [`Interrupts::fifo_watermark`] to [`InterruptPin::Int1`] to get an
+    /// interrupt on PE4. A FIFO stopped in `Fifo` mode is restarted by going
+    /// through `Bypass`.
+    pub fn set_fifo_mode(&mut self, mode: FifoMode, watermark: u8) -> Result<(), E> {
+        let (fm, tr) = match mode {
+            FifoMode::Bypass => (0b00, 0),
+            FifoMode::Fifo => (0b01, 0),
+            FifoMode::Stream => (0b10, 0),
+            FifoMode::StreamToFifo(InterruptPin::Int1) => (0b11, 0),
+            FifoMode::StreamToFifo(InterruptPin::Int2) => (0b11, 1),
+        };
+        let fifo_en = (mode != FifoMode::Bypass) as u8;
+
+        self.modify_register(Register::CTRL_REG5_A, |r| {
+            (r & !0b0100_0000) | (fifo_en << 6)
+        })?;
+        self.write_register(
+            Register::FIFO_CTRL_REG_A,
+            (fm << 6) | (tr << 5) | watermark.min(31),
+        )
+    }
+
+    /// Reads the FIFO fill level and flags
+    pub fn fifo_status(&mut self) -> Result<FifoStatus, E> {
+        let src = self.read_register(Register::FIFO_SRC_REG_A)?;
+        let overrun = src & 0b0100_0000 != 0;
+
+        Ok(FifoStatus {
+            // FSS saturates at 31 with the overrun flag set once full
+            len: if overrun {
+                FIFO_DEPTH
+            } else {
+                (src & 0b0001_1111) as usize
+            },
+            watermark: src & 0b1000_0000 != 0,
+            overrun,
+        })
+    }
+
+    /// Reads all pending samples, up to the length of `samples`, in a single
+    /// I2C transaction
+    ///
+    /// Samples are right-justified like [`RawAccelerometer::accel_raw`],
+    /// oldest first. Returns the number of samples read.
+    ///
+    /// [`RawAccelerometer::accel_raw`]: accelerometer::RawAccelerometer::accel_raw
+    pub fn read_fifo(&mut self, samples: &mut [I16x3]) -> Result<usize, E> {
+        let count = self.fifo_status()?.len.min(samples.len());
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // With the FIFO enabled, the auto-increment wraps from OUT_Z_H_A back
+        // to OUT_X_L_A, popping one sample per six bytes
+        let mut buffer = [0u8; 6 * FIFO_DEPTH];
+        let buffer = &mut buffer[..6 * count];
+        self.i2c.write_read(
+            ADDRESS,
+            &[Register::OUT_X_L_A as u8 | AUTO_INCREMENT],
+            buffer,
+        )?;
+
+        let shift = self.mode.shift();
+        for (sample, bytes) in samples.iter_mut().zip(buffer.chunks(6)) {
+            *sample = I16x3::new(
+                i16::from_le_bytes([bytes[0], bytes[1]]) >> shift,
+                i16::from_le_bytes([bytes[2], bytes[3]]) >> shift,
+                i16::from_le_bytes([bytes[4], bytes[5]]) >> shift,
+            );
+        }
+
+        Ok(count)
+    }
+
+    /// Converts a raw sample to g in the configured range and mode, with
+    /// the calibration applied
+    pub fn to_g(&self, sample: I16x3) -> F32x3 {
+        let sensitivity = self.sensitivity();
+
+        let g = F32x3::new(
+            sample.x as f32 * sensitivity,
+            sample.y as f32 * sensitivity,
+            sample.z as f32 * sensitivity,
+        );
+        match self.calibration {
+            Some(calibration) => calibration.apply(g),
+            None => g,
+        }
+    }
+
+    /// Sets the calibration applied to every reading in g
+    ///
+    /// See [`calibrate_accelerometer`](crate::calibration::calibrate_accelerometer).
+    pub fn set_calibration(&mut self, calibration: Option<AccelCalibration>) {
+        self.calibration = calibration;
+    }
+
+    /// Returns the calibration in use
+    pub fn calibration(&self) -> Option<AccelCalibration> {
+        self.calibration
+    }
+
+    /// Enables click detection and routes it to `config.pin`
+    ///
+    /// Other interrupt sources routed to that pin are left untouched.
+    /// Read the detected taps with [`Accelerometer::tap_event`].
+    pub fn enable_tap_detection(&mut self, config: TapConfig) -> Result<(), E> {
+        let axes = |axes: Axes| ((axes.z as u8) << 4) | ((axes.y as u8) << 2) | (axes.x as u8);
+        let cfg = (axes(config.double) << 1) | axes(config.single);
+
+        let lsb_mg = self.full_scale.generator_lsb_mg(self.variant);
+        let threshold = (f32::from(config.threshold_mg) / lsb_mg + 0.5).min(127.0) as u8;
+
+        let time_limit = self.ms_to_periods(config.time_limit_ms).min(0x7F);
+        let latency = self.ms_to_periods(config.latency_ms);
+        let window = self.ms_to_periods(config.window_ms);
+
+        self.write_register(Register::CLICK_THS_A, threshold)?;
+        self.write_register(Register::TIME_LIMIT_A, time_limit)?;
+        self.write_register(Register::TIME_LATENCY_A, latency)?;
+        self.write_register(Register::TIME_WINDOW_A, window)?;
+        self.write_register(Register::CLICK_CFG_A, cfg)?;
+
+        self.route_click(config.pin, true)
+    }
+
+    /// Disables click detection and its interrupt routing
+    pub fn disable_tap_detection(&mut self) -> Result<(), E> {
+        self.write_register(Register::CLICK_CFG_A, 0)?;
+        self.route_click(InterruptPin::Int1, false)?;
+        self.route_click(InterruptPin::Int2, false)
+    }
+
+    /// Reads and clears the last detected tap
+    ///
+    /// Reading CLICK_SRC_A also releases the interrupt output.
+    pub fn tap_event(&mut self) -> Result<Option<TapEvent>, E> {
+        let src = self.read_register(Register::CLICK_SRC_A)?;
+        if src & 0b0100_0000 == 0 {
+            return Ok(None);
+        }
+
+        let axis = if src & 0b001 != 0 {
+            Axis::X
+        } else if src & 0b010 != 0 {
+            Axis::Y
+        } else {
+            Axis::Z
+        };
+
+        Ok(Some(TapEvent {
+            double: src & 0b0010_0000 != 0,
+            axis,
+            negative: src & 0b0000_1000 != 0,
+        }))
+    }
+
+    /// Configures an inertial interrupt generator and routes it to
+    /// `config.pin`
+    ///
+    /// Other interrupt sources routed to that pin are left untouched.
+    /// Read the events with [`Accelerometer::generator_event`].
+    pub fn enable_generator(
         &mut self,
-    ) -> Result<accelerometer::vector::I8x3, accelerometer::Error<Self::Error>> {
-        self.lis302dl.accel_raw()
+        generator: Generator,
+        config: GeneratorConfig,
+    ) -> Result<(), E> {
+        let (cfg, ths, duration) = generator.registers();
+        let (four_d, latch) = generator.ctrl_reg5_bits();
+
+        let value = match config.mode {
+            GeneratorMode::Or(events) => events.bits(),
+            GeneratorMode::And(events) => 0b1000_0000 | events.bits(),
+            // 6D recognition needs the events of all axes enabled
+            GeneratorMode::Movement { .. } => 0b0111_1111,
+            GeneratorMode::Position { .. } => 0b1111_1111,
+        };
+        let use_four_d = match config.mode {
+            GeneratorMode::Movement { four_d } | GeneratorMode::Position { four_d } => four_d,
+            _ => false,
+        };
+
+        let lsb_mg = self.full_scale.generator_lsb_mg(self.variant);
+        let threshold = (f32::from(config.threshold_mg) / lsb_mg + 0.5).min(127.0) as u8;
+
+        self.write_register(ths, threshold)?;
+        self.write_register(duration, self.ms_to_periods(config.duration_ms).min(0x7F))?;
+        self.modify_register(Register::CTRL_REG5_A, |r| {
+            let mut r = r & !(four_d | latch);
+            if use_four_d {
+                r |= four_d;
+            }
+            if config.latch {
+                r |= latch;
+            }
+            r
+        })?;
+        self.write_register(cfg, value)?;
+
+        self.route_generator(generator, config.pin, true)
+    }
+
+    /// Disables an inertial interrupt generator and its interrupt routing
+    pub fn disable_generator(&mut self, generator: Generator) -> Result<(), E> {
+        let (cfg, _, _) = generator.registers();
+        self.write_register(cfg, 0)?;
+        self.route_generator(generator, InterruptPin::Int1, false)?;
+        self.route_generator(generator, InterruptPin::Int2, false)
+    }
+
+    /// Reads the state of an inertial interrupt generator
+    ///
+    /// Returns `None` if it has not fired. Reading also releases a latched
+    /// interrupt output.
+    pub fn generator_event(&mut self, generator: Generator) -> Result<Option<GeneratorEvent>, E> {
+        let src = match generator {
+            Generator::One => self.read_register(Register::INT1_SRC_A)?,
+            Generator::Two => self.read_register(Register::INT2_SRC_A)?,
+        };
+        if src & 0b0100_0000 == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(GeneratorEvent {
+            generator,
+            axes: AxisEvents::from_bits(src),
+        }))
+    }
+
+    /// Sets or clears the bit of an inertial interrupt generator on an
+    /// interrupt output
+    fn route_generator(
+        &mut self,
+        generator: Generator,
+        pin: InterruptPin,
+        enabled: bool,
+    ) -> Result<(), E> {
+        let register = match pin {
+            InterruptPin::Int1 => Register::CTRL_REG3_A,
+            InterruptPin::Int2 => Register::CTRL_REG6_A,
+        };
+        let bit = match generator {
+            Generator::One => 0b0100_0000,
+            Generator::Two => 0b0010_0000,
+        };
+        self.modify_register(register, |r| if enabled { r | bit } else { r & !bit })
+    }
+
+    /// Sets or clears the click bit of an interrupt output
+    fn route_click(&mut self, pin: InterruptPin, enabled: bool) -> Result<(), E> {
+        let register = match pin {
+            InterruptPin::Int1 => Register::CTRL_REG3_A,
+            InterruptPin::Int2 => Register::CTRL_REG6_A,
+        };
+        self.modify_register(register, |r| (r & !0b1000_0000) | ((enabled as u8) << 7))
+    }
+
+    /// Number of output data rate periods in `ms`, saturating at 255
+    fn ms_to_periods(&self, ms: u16) -> u8 {
+        let periods = f32::from(ms) * self.data_rate.hz() / 1000.0 + 0.5;
+        if periods >= 255.0 {
+            255
+        } else {
+            periods as u8
+        }
+    }
+
+    /// Reads the latest right-justified sample
+    pub(crate) fn read_sample(&mut self) -> Result<I16x3, E> {
+        let mut buffer = [0u8; 6];
+        self.i2c.write_read(
+            ADDRESS,
+            &[Register::OUT_X_L_A as u8 | AUTO_INCREMENT],
+            &mut buffer,
+        )?;
+
+        let shift = self.mode.shift();
+        Ok(I16x3::new(
+            i16::from_le_bytes([buffer[0], buffer[1]]) >> shift,
+            i16::from_le_bytes([buffer[2], buffer[3]]) >> shift,
+            i16::from_le_bytes([buffer[4], buffer[5]]) >> shift,
+        ))
+    }
+
+    /// Sensitivity in the configured range and mode, in g/LSB
+    fn sensitivity(&self) -> f32 {
+        let high_resolution = self.full_scale.sensitivity(self.variant);
+
+        high_resolution * (1 << (self.mode.shift() - 4)) as f32
+    }
+
+    fn write_ctrl_reg1(&mut self) -> Result<(), E> {
+        let odr = if self.powered_down {
+            0
+        } else {
+            self.data_rate.odr()
+        };
+        let lp_en = (self.mode == Mode::LowPower) as u8;
+
+        let value = (odr << 4)
+            | (lp_en << 3)
+            | ((self.axes.z as u8) << 2)
+            | ((self.axes.y as u8) << 1)
+            | (self.axes.x as u8);
+        self.write_register(Register::CTRL_REG1_A, value)
+    }
+
+    fn write_register(&mut self, register: Register, value: u8) -> Result<(), E> {
+        self.i2c.write(ADDRESS, &[register as u8, value])
+    }
+
+    fn read_register(&mut self, register: Register) -> Result<u8, E> {
+        let mut buffer = [0u8];
+        self.i2c
+            .write_read(ADDRESS, &[register as u8], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    fn modify_register<F>(&mut self, register: Register, f: F) -> Result<(), E>
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        let value = self.read_register(register)?;
+        self.write_register(register, f(value))
+    }
+}
+
+impl Generator {
+    /// CFG, THS and DURATION registers
+    fn registers(self) -> (Register, Register, Register) {
+        match self {
+            Generator::One => (
+                Register::INT1_CFG_A,
+                Register::INT1_THS_A,
+                Register::INT1_DURATION_A,
+            ),
+            Generator::Two => (
+                Register::INT2_CFG_A,
+                Register::INT2_THS_A,
+                Register::INT2_DURATION_A,
+            ),
+        }
+    }
+
+    /// D4D_INTx and LIR_INTx bits of CTRL_REG5_A
+    fn ctrl_reg5_bits(self) -> (u8, u8) {
+        match self {
+            Generator::One => (0b0000_0100, 0b0000_1000),
+            Generator::Two => (0b0000_0001, 0b0000_0010),
+        }
     }
 }
 
-impl accelerometer::Accelerometer for Accelerometer {
-    type Error = spi::Error;
+impl<I2C, E> accelerometer::RawAccelerometer<I16x3> for Accelerometer<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+{
+    type Error = E;
+
+    /// Returns the right-justified acceleration sample, 8, 10 or 12 bits wide
+    /// depending on the configured mode
+    fn accel_raw(&mut self) -> Result<I16x3, accelerometer::Error<Self::Error>> {
+        Ok(self.read_sample()?)
+    }
+}
+
+impl<I2C, E> accelerometer::Accelerometer for Accelerometer<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+{
+    type Error = E;
+
+    /// Returns the configured output data rate, 0 while powered down
     fn sample_rate(&mut self) -> Result<f32, accelerometer::Error<Self::Error>> {
-        self.lis302dl.sample_rate()
+        if self.powered_down {
+            Ok(0.0)
+        } else {
+            Ok(self.data_rate.hz())
+        }
     }
 
-    fn accel_norm(
-        &mut self,
-    ) -> Result<accelerometer::vector::F32x3, accelerometer::Error<Self::Error>> {
-        self.lis302dl.accel_norm()
+    fn accel_norm(&mut self) -> Result<F32x3, accelerometer::Error<Self::Error>> {
+        let raw: I16x3 = accelerometer::RawAccelerometer::accel_raw(self)?;
+        Ok(self.to_g(raw))
     }
 }
@@ -0,0 +1,431 @@
+//! Board-level access to every on-board device
+
+use core::fmt;
+
+use crate::hal::gpio::{gpioa, gpiob, gpioc, gpiod, gpioe};
+use crate::hal::gpio::{Floating, Input, Output, PushPull};
+use crate::hal::i2c;
+use crate::hal::prelude::*;
+use crate::hal::rcc;
+use crate::hal::spi;
+use crate::hal::stm32;
+use crate::hal::syscfg::{SysCfg, SysCfgExt};
+
+use crate::accelerometer::Accelerometer;
+use crate::bus::{self, I2c1Proxy};
+use crate::button::{self, Button};
+use crate::clocks::{ClockTree, Preset};
+use crate::compass::Compass;
+use crate::gyroscope::{self, Gyroscope};
+use crate::led::Leds;
+use crate::revision::{self, BoardRevision, SensorSet};
+
+/// Errors while bringing up the board
+pub enum Error {
+    /// The PAC and core peripherals were already taken
+    AlreadyTaken,
+    /// The core peripherals were already taken, the PAC peripherals are
+    /// handed back for [`Board::new`]
+    CoreTaken(stm32::Peripherals),
+    /// The PAC peripherals were already taken, the core peripherals are
+    /// handed back for [`Board::new`]
+    DeviceTaken(cortex_m::Peripherals),
+    /// The e-compass could not be identified on I2C1
+    Probe(revision::Error<i2c::Error>),
+    /// The accelerometer did not respond on I2C1
+    Accelerometer(i2c::Error),
+    /// The magnetometer did not respond on I2C1
+    Compass(i2c::Error),
+    /// The gyroscope could not be initialized on SPI1
+    Gyroscope(gyroscope::Error<spi::Error>),
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AlreadyTaken => f.write_str("AlreadyTaken"),
+            Error::CoreTaken(_) => f.write_str("CoreTaken"),
+            Error::DeviceTaken(_) => f.write_str("DeviceTaken"),
+            Error::Probe(e) => f.debug_tuple("Probe").field(e).finish(),
+            Error::Accelerometer(e) => f.debug_tuple("Accelerometer").field(e).finish(),
+            Error::Compass(e) => f.debug_tuple("Compass").field(e).finish(),
+            Error::Gyroscope(e) => f.debug_tuple("Gyroscope").field(e).finish(),
+        }
+    }
+}
+
+/// CS43L22 audio DAC resources
+pub struct Audio {
+    /// Control port, shared with the e-compass on I2C1 (address 0x4A)
+    pub i2c: I2c1Proxy,
+    /// Active low reset of the DAC
+    pub reset: gpiod::PD4<Output<PushPull>>,
+    /// I2S3 word select
+    pub ws: gpioa::PA4<Input<Floating>>,
+    /// I2S3 master clock
+    pub mck: gpioc::PC7<Input<Floating>>,
+    /// I2S3 bit clock
+    pub ck: gpioc::PC10<Input<Floating>>,
+    /// I2S3 serial data
+    pub sd: gpioc::PC12<Input<Floating>>,
+    /// I2S3 peripheral
+    pub spi3: stm32::SPI3,
+}
+
+/// MP45DT02 MEMS microphone resources
+pub struct Microphone {
+    /// PDM clock (I2S2 CK)
+    pub clk: gpiob::PB10<Input<Floating>>,
+    /// PDM data (I2S2 SD)
+    pub dout: gpioc::PC3<Input<Floating>>,
+    /// I2S2 peripheral
+    pub spi2: stm32::SPI2,
+}
+
+/// USB OTG FS connector resources
+pub struct Usb {
+    /// VBUS sensing
+    pub vbus: gpioa::PA9<Input<Floating>>,
+    /// OTG ID
+    pub id: gpioa::PA10<Input<Floating>>,
+    /// D-
+    pub dm: gpioa::PA11<Input<Floating>>,
+    /// D+
+    pub dp: gpioa::PA12<Input<Floating>>,
+    /// Active low enable of the VBUS power switch
+    pub power_switch: gpioc::PC0<Output<PushPull>>,
+    /// Active low over-current flag of the VBUS power switch
+    pub over_current: gpiod::PD5<Input<Floating>>,
+    /// OTG FS core registers
+    pub otg_fs_global: stm32::OTG_FS_GLOBAL,
+    /// OTG FS device registers
+    pub otg_fs_device: stm32::OTG_FS_DEVICE,
+    /// OTG FS power and clock gating registers
+    pub otg_fs_pwrclk: stm32::OTG_FS_PWRCLK,
+}
+
+/// Interrupt lines of the MEMS sensors
+pub struct MemsInterrupts {
+    /// Gyroscope INT1
+    pub gyro_int1: gpioe::PE0<Input<Floating>>,
+    /// Gyroscope INT2/DRDY
+    pub gyro_int2: gpioe::PE1<Input<Floating>>,
+    /// Magnetometer DRDY
+    pub mag_drdy: gpioe::PE2<Input<Floating>>,
+    /// Accelerometer INT1
+    pub accel_int1: gpioe::PE4<Input<Floating>>,
+    /// Accelerometer INT2
+    pub accel_int2: gpioe::PE5<Input<Floating>>,
+}
+
+/// Header pins not connected to any on-board device
+///
+/// The SWD/SWO pins (PA13, PA14, PB3) and the HSE input (PH0, PH1) are
+/// intentionally left out.
+pub struct HeaderPins {
+    pub pa1: gpioa::PA1<Input<Floating>>,
+    pub pa2: gpioa::PA2<Input<Floating>>,
+    pub pa3: gpioa::PA3<Input<Floating>>,
+    pub pa8: gpioa::PA8<Input<Floating>>,
+    pub pa15: gpioa::PA15<Input<Floating>>,
+    pub pb0: gpiob::PB0<Input<Floating>>,
+    pub pb1: gpiob::PB1<Input<Floating>>,
+    pub pb2: gpiob::PB2<Input<Floating>>,
+    pub pb4: gpiob::PB4<Input<Floating>>,
+    pub pb5: gpiob::PB5<Input<Floating>>,
+    pub pb7: gpiob::PB7<Input<Floating>>,
+    pub pb8: gpiob::PB8<Input<Floating>>,
+    pub pb12: gpiob::PB12<Input<Floating>>,
+    pub pb13: gpiob::PB13<Input<Floating>>,
+    pub pb14: gpiob::PB14<Input<Floating>>,
+    pub pb15: gpiob::PB15<Input<Floating>>,
+    pub pc1: gpioc::PC1<Input<Floating>>,
+    pub pc2: gpioc::PC2<Input<Floating>>,
+    pub pc4: gpioc::PC4<Input<Floating>>,
+    pub pc5: gpioc::PC5<Input<Floating>>,
+    pub pc6: gpioc::PC6<Input<Floating>>,
+    pub pc8: gpioc::PC8<Input<Floating>>,
+    pub pc9: gpioc::PC9<Input<Floating>>,
+    pub pc11: gpioc::PC11<Input<Floating>>,
+    pub pc13: gpioc::PC13<Input<Floating>>,
+    pub pc14: gpioc::PC14<Input<Floating>>,
+    pub pc15: gpioc::PC15<Input<Floating>>,
+    pub pd0: gpiod::PD0<Input<Floating>>,
+    pub pd1: gpiod::PD1<Input<Floating>>,
+    pub pd2: gpiod::PD2<Input<Floating>>,
+    pub pd3: gpiod::PD3<Input<Floating>>,
+    pub pd6: gpiod::PD6<Input<Floating>>,
+    pub pd7: gpiod::PD7<Input<Floating>>,
+    pub pd8: gpiod::PD8<Input<Floating>>,
+    pub pd9: gpiod::PD9<Input<Floating>>,
+    pub pd10: gpiod::PD10<Input<Floating>>,
+    pub pd11: gpiod::PD11<Input<Floating>>,
+    pub pe6: gpioe::PE6<Input<Floating>>,
+    pub pe7: gpioe::PE7<Input<Floating>>,
+    pub pe8: gpioe::PE8<Input<Floating>>,
+    pub pe9: gpioe::PE9<Input<Floating>>,
+    pub pe10: gpioe::PE10<Input<Floating>>,
+    pub pe11: gpioe::PE11<Input<Floating>>,
+    pub pe12: gpioe::PE12<Input<Floating>>,
+    pub pe13: gpioe::PE13<Input<Floating>>,
+    pub pe14: gpioe::PE14<Input<Floating>>,
+    pub pe15: gpioe::PE15<Input<Floating>>,
+}
+
+/// Device peripherals not used by any on-board device
+pub struct DevicePeripherals {
+    pub adc1: stm32::ADC1,
+    pub crc: stm32::CRC,
+    pub dbgmcu: stm32::DBGMCU,
+    pub dma1: stm32::DMA1,
+    pub dma2: stm32::DMA2,
+    pub exti: stm32::EXTI,
+    pub flash: stm32::FLASH,
+    pub i2c2: stm32::I2C2,
+    pub i2c3: stm32::I2C3,
+    pub iwdg: stm32::IWDG,
+    pub pwr: stm32::PWR,
+    pub rtc: stm32::RTC,
+    pub sdio: stm32::SDIO,
+    pub spi4: stm32::SPI4,
+    pub spi5: stm32::SPI5,
+    pub syscfg: SysCfg,
+    pub tim1: stm32::TIM1,
+    pub tim2: stm32::TIM2,
+    pub tim3: stm32::TIM3,
+    pub tim4: stm32::TIM4,
+    pub tim5: stm32::TIM5,
+    pub tim9: stm32::TIM9,
+    pub tim10: stm32::TIM10,
+    pub tim11: stm32::TIM11,
+    pub usart1: stm32::USART1,
+    pub usart2: stm32::USART2,
+    pub usart6: stm32::USART6,
+    pub wwdg: stm32::WWDG,
+}
+
+/// STM32F411E-DISCO board with all of its on-board devices
+pub struct Board {
+    /// User LEDs LD3 to LD6
+    pub leds: Leds,
+    /// User button B1, with EXTI0 configured on both edges
+    pub button: Button,
+    /// MEMS sensors fitted on this board
+    pub sensors: SensorSet,
+    /// LSM303DLHC or LSM303AGR accelerometer
+    pub accelerometer: Accelerometer<I2c1Proxy>,
+    /// LSM303DLHC or LSM303AGR magnetometer
+    pub compass: Compass<I2c1Proxy>,
+    /// L3GD20 or I3G4250D gyroscope
+    pub gyroscope: Gyroscope,
+    /// MEMS sensor interrupt lines
+    pub mems_interrupts: MemsInterrupts,
+    /// CS43L22 audio DAC
+    pub audio: Audio,
+    /// MP45DT02 microphone
+    pub microphone: Microphone,
+    /// USB OTG FS connector
+    pub usb: Usb,
+    /// Unused header pins
+    pub pins: HeaderPins,
+    /// Unused device peripherals
+    pub device: DevicePeripherals,
+    /// Core peripherals
+    pub core: cortex_m::Peripherals,
+    /// Frozen clock configuration
+    pub clocks: rcc::Clocks,
+    /// Description of the clock configuration
+    pub clock_tree: ClockTree,
+}
+
+impl Board {
+    /// Takes the PAC and core peripherals and brings up the board with the
+    /// `Performance` clock preset
+    ///
+    /// Returns `Error::AlreadyTaken` on every call after the first one. If
+    /// only one of the two sets was still available, it is returned in the
+    /// error instead of being dropped.
+    pub fn take() -> Result<Self, Error> {
+        Self::take_with(Preset::Performance)
+    }
+
+    /// Takes the PAC and core peripherals and brings up the board with the
+    /// given clock preset
+    pub fn take_with(preset: Preset) -> Result<Self, Error> {
+        match (stm32::Peripherals::take(), cortex_m::Peripherals::take()) {
+            (Some(dp), Some(cp)) => Self::new(dp, cp, preset),
+            (Some(dp), None) => Err(Error::CoreTaken(dp)),
+            (None, Some(cp)) => Err(Error::DeviceTaken(cp)),
+            (None, None) => Err(Error::AlreadyTaken),
+        }
+    }
+
+    /// Brings up the board from the PAC and core peripherals
+    pub fn new(
+        dp: stm32::Peripherals,
+        cp: cortex_m::Peripherals,
+        preset: Preset,
+    ) -> Result<Self, Error> {
+        let (clocks, clock_tree) = preset.freeze(dp.RCC);
+
+        let gpioa = dp.GPIOA.split();
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+        let gpiod = dp.GPIOD.split();
+        let gpioe = dp.GPIOE.split();
+
+        let mut syscfg = dp.SYSCFG.constrain();
+        let mut exti = dp.EXTI;
+        let button = Button::new(gpioa.pa0, &mut syscfg, &mut exti, button::Config::default());
+
+        let i2c = bus::share_i2c1(bus::i2c1(gpiob.pb6, gpiob.pb9, dp.I2C1, clocks));
+
+        let ecompass = revision::probe_ecompass(&mut i2c.clone()).map_err(Error::Probe)?;
+        let accelerometer =
+            Accelerometer::from_i2c(i2c.clone(), ecompass).map_err(Error::Accelerometer)?;
+        let compass = Compass::from_i2c(i2c.clone(), ecompass).map_err(Error::Compass)?;
+        let gyroscope = Gyroscope::new(gpioa.pa5, gpioa.pa6, gpioa.pa7, gpioe.pe3, dp.SPI1, clocks)
+            .map_err(Error::Gyroscope)?;
+
+        // Keep the audio DAC in reset until a driver brings it up
+        let mut reset = gpiod.pd4.into_push_pull_output();
+        reset.set_low().ok();
+
+        // Keep VBUS switched off
+        let mut power_switch = gpioc.pc0.into_push_pull_output();
+        power_switch.set_high().ok();
+
+        let sensors = SensorSet {
+            ecompass,
+            gyroscope: gyroscope.variant(),
+        };
+
+        Ok(Board {
+            leds: Leds::new(gpiod.pd12, gpiod.pd13, gpiod.pd14, gpiod.pd15),
+            button,
+            sensors,
+            accelerometer,
+            compass,
+            gyroscope,
+            mems_interrupts: MemsInterrupts {
+                gyro_int1: gpioe.pe0,
+                gyro_int2: gpioe.pe1,
+                mag_drdy: gpioe.pe2,
+                accel_int1: gpioe.pe4,
+                accel_int2: gpioe.pe5,
+            },
+            audio: Audio {
+                i2c,
+                reset,
+                ws: gpioa.pa4,
+                mck: gpioc.pc7,
+                ck: gpioc.pc10,
+                sd: gpioc.pc12,
+                spi3: dp.SPI3,
+            },
+            microphone: Microphone {
+                clk: gpiob.pb10,
+                dout: gpioc.pc3,
+                spi2: dp.SPI2,
+            },
+            usb: Usb {
+                vbus: gpioa.pa9,
+                id: gpioa.pa10,
+                dm: gpioa.pa11,
+                dp: gpioa.pa12,
+                power_switch,
+                over_current: gpiod.pd5,
+                otg_fs_global: dp.OTG_FS_GLOBAL,
+                otg_fs_device: dp.OTG_FS_DEVICE,
+                otg_fs_pwrclk: dp.OTG_FS_PWRCLK,
+            },
+            pins: HeaderPins {
+                pa1: gpioa.pa1,
+                pa2: gpioa.pa2,
+                pa3: gpioa.pa3,
+                pa8: gpioa.pa8,
+                pa15: gpioa.pa15,
+                pb0: gpiob.pb0,
+                pb1: gpiob.pb1,
+                pb2: gpiob.pb2,
+                pb4: gpiob.pb4,
+                pb5: gpiob.pb5,
+                pb7: gpiob.pb7,
+                pb8: gpiob.pb8,
+                pb12: gpiob.pb12,
+                pb13: gpiob.pb13,
+                pb14: gpiob.pb14,
+                pb15: gpiob.pb15,
+                pc1: gpioc.pc1,
+                pc2: gpioc.pc2,
+                pc4: gpioc.pc4,
+                pc5: gpioc.pc5,
+                pc6: gpioc.pc6,
+                pc8: gpioc.pc8,
+                pc9: gpioc.pc9,
+                pc11: gpioc.pc11,
+                pc13: gpioc.pc13,
+                pc14: gpioc.pc14,
+                pc15: gpioc.pc15,
+                pd0: gpiod.pd0,
+                pd1: gpiod.pd1,
+                pd2: gpiod.pd2,
+                pd3: gpiod.pd3,
+                pd6: gpiod.pd6,
+                pd7: gpiod.pd7,
+                pd8: gpiod.pd8,
+                pd9: gpiod.pd9,
+                pd10: gpiod.pd10,
+                pd11: gpiod.pd11,
+                pe6: gpioe.pe6,
+                pe7: gpioe.pe7,
+                pe8: gpioe.pe8,
+                pe9: gpioe.pe9,
+                pe10: gpioe.pe10,
+                pe11: gpioe.pe11,
+                pe12: gpioe.pe12,
+                pe13: gpioe.pe13,
+                pe14: gpioe.pe14,
+                pe15: gpioe.pe15,
+            },
+            device: DevicePeripherals {
+                adc1: dp.ADC1,
+                crc: dp.CRC,
+                dbgmcu: dp.DBGMCU,
+                dma1: dp.DMA1,
+                dma2: dp.DMA2,
+                exti,
+                flash: dp.FLASH,
+                i2c2: dp.I2C2,
+                i2c3: dp.I2C3,
+                iwdg: dp.IWDG,
+                pwr: dp.PWR,
+                rtc: dp.RTC,
+                sdio: dp.SDIO,
+                spi4: dp.SPI4,
+                spi5: dp.SPI5,
+                syscfg,
+                tim1: dp.TIM1,
+                tim2: dp.TIM2,
+                tim3: dp.TIM3,
+                tim4: dp.TIM4,
+                tim5: dp.TIM5,
+                tim9: dp.TIM9,
+                tim10: dp.TIM10,
+                tim11: dp.TIM11,
+                usart1: dp.USART1,
+                usart2: dp.USART2,
+                usart6: dp.USART6,
+                wwdg: dp.WWDG,
+            },
+            core: cp,
+            clocks,
+            clock_tree,
+        })
+    }
+
+    /// Hardware revision derived from the detected sensors
+    pub fn revision(&self) -> BoardRevision {
+        self.sensors.revision()
+    }
+}
@@ -0,0 +1,104 @@
+//! On-board I2C1 bus shared by the e-compass and the CS43L22 audio DAC
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::hal::gpio;
+use crate::hal::gpio::gpiob;
+use crate::hal::i2c;
+use crate::hal::prelude::*;
+use crate::hal::rcc;
+use crate::hal::stm32;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+/// I2C1 bus as wired on the board (PB6 SCL, PB9 SDA)
+pub type I2c1 = i2c::I2c<
+    stm32::I2C1,
+    (
+        gpiob::PB6<gpio::AlternateOD<gpio::AF4>>,
+        gpiob::PB9<gpio::AlternateOD<gpio::AF4>>,
+    ),
+>;
+
+static I2C1_BUS: Mutex<RefCell<Option<I2c1>>> = Mutex::new(RefCell::new(None));
+
+/// Configures I2C1 on PB6/PB9 in fast mode (400 kHz)
+pub fn i2c1(
+    scl: gpiob::PB6<gpio::Input<gpio::Floating>>,
+    sda: gpiob::PB9<gpio::Input<gpio::Floating>>,
+    i2c1: stm32::I2C1,
+    clocks: rcc::Clocks,
+) -> I2c1 {
+    let scl = scl
+        .into_alternate_af4()
+        .internal_pull_up(true)
+        .set_open_drain();
+    let sda = sda
+        .into_alternate_af4()
+        .internal_pull_up(true)
+        .set_open_drain();
+
+    i2c::I2c::i2c1(i2c1, (scl, sda), 400.khz(), clocks)
+}
+
+/// Moves I2C1 into shared storage and returns a first handle to it
+///
+/// Only called by [`Board`](crate::board::Board), which owns the I2C1
+/// peripheral. Further handles are obtained by cloning the returned proxy.
+pub(crate) fn share_i2c1(i2c: I2c1) -> I2c1Proxy {
+    interrupt::free(|cs| {
+        I2C1_BUS.borrow(cs).replace(Some(i2c));
+    });
+
+    I2c1Proxy { _private: () }
+}
+
+/// Handle to the shared I2C1 bus
+///
+/// Every transaction runs inside a critical section, so proxies can be used
+/// from both thread and interrupt context.
+#[derive(Clone)]
+pub struct I2c1Proxy {
+    _private: (),
+}
+
+impl I2c1Proxy {
+    fn with_bus<R>(&mut self, f: impl FnOnce(&mut I2c1) -> R) -> R {
+        interrupt::free(|cs| {
+            let mut bus = I2C1_BUS.borrow(cs).borrow_mut();
+            // A proxy only exists once the bus has been shared
+            f(bus.as_mut().unwrap())
+        })
+    }
+}
+
+impl Write for I2c1Proxy {
+    type Error = i2c::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.write(address, bytes))
+    }
+}
+
+impl Read for I2c1Proxy {
+    type Error = i2c::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.read(address, buffer))
+    }
+}
+
+impl WriteRead for I2c1Proxy {
+    type Error = i2c::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.with_bus(|bus| bus.write_read(address, bytes, buffer))
+    }
+}
@@ -0,0 +1,278 @@
+//! On-board user button B1
+//!
+//! The button is debounced in software. Call [`Button::tick`] from a periodic
+//! timer interrupt and [`Button::on_interrupt`] from the `EXTI0` handler; the
+//! latter tells when the timer needs to run at all, so the tick can be
+//! stopped while the button is idle.
+
+use core::convert::Infallible;
+
+use crate::hal::gpio::gpioa::PA0;
+use crate::hal::gpio::{Edge, ExtiPin, Floating, Input, PullDown};
+use crate::hal::prelude::*;
+use crate::hal::stm32::EXTI;
+use crate::hal::syscfg::SysCfg;
+
+use embedded_hal::digital::v2::InputPin;
+
+/// Number of events the queue holds before dropping the oldest one
+const QUEUE_LEN: usize = 8;
+
+/// Button gestures
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The button went down
+    Pressed,
+    /// The button went up
+    Released,
+    /// Short press not followed by a second one within the double-click
+    /// window
+    Click,
+    /// Two short presses within the double-click window
+    DoubleClick,
+    /// Press held for at least the long-press time, with the held time in ms
+    LongPress(u32),
+}
+
+/// Debouncing and gesture timing, all in milliseconds
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Period at which [`Button::tick`] is called
+    pub tick_ms: u32,
+    /// Time the input must be stable before a level change is accepted
+    pub debounce_ms: u32,
+    /// Maximum time from the release of a click to the next press for both
+    /// to form a double click
+    pub double_click_ms: u32,
+    /// Minimum time held to report a long press instead of a click
+    pub long_press_ms: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tick_ms: 1,
+            debounce_ms: 20,
+            double_click_ms: 300,
+            long_press_ms: 800,
+        }
+    }
+}
+
+/// Fixed size event queue, dropping the oldest event when full
+struct Queue {
+    events: [Event; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl Queue {
+    const fn new() -> Self {
+        Queue {
+            events: [Event::Released; QUEUE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        let tail = (self.head + self.len) % QUEUE_LEN;
+        self.events[tail] = event;
+
+        if self.len == QUEUE_LEN {
+            self.head = (self.head + 1) % QUEUE_LEN;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        Some(event)
+    }
+}
+
+/// User button B1 on PA0 (active high)
+pub struct Button {
+    pin: PA0<Input<PullDown>>,
+    config: Config,
+    callback: Option<fn(Event)>,
+    queue: Queue,
+    pressed: bool,
+    bounce_ms: u32,
+    held_ms: u32,
+    click_pending: bool,
+    since_release_ms: u32,
+    active: bool,
+}
+
+impl Button {
+    /// Configures PA0 as input with an interrupt on both edges on EXTI0
+    ///
+    /// The `EXTI0` interrupt still has to be unmasked in the NVIC.
+    pub fn new(
+        pa0: PA0<Input<Floating>>,
+        syscfg: &mut SysCfg,
+        exti: &mut EXTI,
+        config: Config,
+    ) -> Self {
+        let mut pin = pa0.into_pull_down_input();
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, Edge::RISING_FALLING);
+        pin.enable_interrupt(exti);
+
+        Button {
+            pin,
+            config,
+            callback: None,
+            queue: Queue::new(),
+            pressed: false,
+            bounce_ms: 0,
+            held_ms: 0,
+            click_pending: false,
+            since_release_ms: 0,
+            active: false,
+        }
+    }
+
+    /// Registers a function called with every event as it is detected
+    ///
+    /// The callback runs in the context calling [`Button::tick`], usually a
+    /// timer interrupt, so it must be short and must not block. Events are
+    /// queued for [`Button::poll`] as well.
+    pub fn set_callback(&mut self, callback: Option<fn(Event)>) {
+        self.callback = callback;
+    }
+
+    /// Returns the oldest queued event
+    pub fn poll(&mut self) -> Option<Event> {
+        self.queue.pop()
+    }
+
+    /// Returns `true` while [`Button::tick`] needs to be called
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Returns the debounced button state
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Handles the `EXTI0` interrupt
+    ///
+    /// Clears the pending bit and marks the button as active, so that
+    /// [`Button::tick`] reports it needs to be called again.
+    pub fn on_interrupt(&mut self) {
+        self.pin.clear_interrupt_pending_bit();
+        self.active = true;
+    }
+
+    /// Advances debouncing and gesture detection by one tick period
+    ///
+    /// Returns `false` once the button is idle, i.e. released with no click
+    /// waiting for a possible second press, so the tick timer can be stopped
+    /// until the next `EXTI0` interrupt.
+    pub fn tick(&mut self) -> bool {
+        let tick = self.config.tick_ms;
+        let level = self.pin.is_high().unwrap_or(false);
+
+        if level != self.pressed {
+            self.bounce_ms += tick;
+            if self.bounce_ms >= self.config.debounce_ms {
+                self.bounce_ms = 0;
+                self.pressed = level;
+                if level {
+                    self.on_press();
+                } else {
+                    self.on_release();
+                }
+            }
+        } else {
+            self.bounce_ms = 0;
+        }
+
+        if self.pressed {
+            self.held_ms += tick;
+        }
+
+        // The window closes once the second press is debounced, however
+        // long that press is then held
+        if self.click_pending && !self.pressed {
+            self.since_release_ms += tick;
+            if self.since_release_ms >= self.config.double_click_ms {
+                self.click_pending = false;
+                self.emit(Event::Click);
+            }
+        }
+
+        self.active = self.pressed || self.click_pending || self.bounce_ms > 0;
+        self.active
+    }
+
+    fn on_press(&mut self) {
+        self.held_ms = 0;
+        self.emit(Event::Pressed);
+    }
+
+    fn on_release(&mut self) {
+        self.emit(Event::Released);
+
+        if self.held_ms >= self.config.long_press_ms {
+            if self.click_pending {
+                self.click_pending = false;
+                self.emit(Event::Click);
+            }
+            self.emit(Event::LongPress(self.held_ms));
+        } else if self.click_pending {
+            self.click_pending = false;
+            self.emit(Event::DoubleClick);
+        } else {
+            self.click_pending = true;
+            self.since_release_ms = 0;
+        }
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.queue.push(event);
+        if let Some(callback) = self.callback {
+            callback(event);
+        }
+    }
+}
+
+/// Reads the debounced state, high while the button is pressed
+impl InputPin for Button {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.pressed)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.pressed)
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_one::digital::ErrorType for Button {
+    type Error = Infallible;
+}
+
+/// Reads the debounced state, high while the button is pressed
+#[cfg(feature = "eh1")]
+impl embedded_hal_one::digital::InputPin for Button {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.pressed)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.pressed)
+    }
+}
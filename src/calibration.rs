@@ -0,0 +1,1476 @@
+//! Sensor calibration
+//!
+//! Calibrations serialize to a fixed size byte array, framed by a magic word
+//! and a checksum, so they can be kept in a flash sector and restored at
+//! boot. Erased or corrupted flash is rejected by `from_bytes`.
+
+use core::fmt::Debug;
+
+use accelerometer::vector::F32x3;
+
+use crate::accelerometer::Accelerometer;
+use crate::led::{LedColor, Leds};
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// 3×3 matrix, row-major
+pub type Matrix3 = [[f32; 3]; 3];
+
+/// Identity matrix
+pub const IDENTITY: Matrix3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Samples averaged for each of the six positions
+const SAMPLES: usize = 64;
+
+/// Period between two samples while calibrating, in ms
+const SAMPLE_PERIOD_MS: u16 = 10;
+
+/// Maximum deviation from the mean, in g, for the board to count as still
+const STILL_G: f32 = 0.03;
+
+/// Minimum gravity along the expected axis, in g, for a position to count
+const ALIGNED_G: f32 = 0.8;
+
+/// Bursts taken in a position before giving up, about 30 s
+const ATTEMPTS: u32 = 50;
+
+/// Errors of the interactive calibration routines
+#[derive(Debug)]
+pub enum Error<E> {
+    /// I2C bus error
+    I2c(E),
+    /// The board was not held still in the position in time
+    Timeout(Position),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Error::I2c(error)
+    }
+}
+
+/// Static orientations of the six-position calibration
+///
+/// Each position names the axis that points up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Position {
+    /// Flat, components side up
+    ZUp,
+    /// Flat, components side down
+    ZDown,
+    /// Standing on the green LED edge
+    XUp,
+    /// Standing on the red LED edge
+    XDown,
+    /// Standing on the blue LED edge
+    YUp,
+    /// Standing on the orange LED edge
+    YDown,
+}
+
+impl Position {
+    /// All positions in the order they are requested
+    pub const ALL: [Position; 6] = [
+        Position::ZUp,
+        Position::ZDown,
+        Position::XUp,
+        Position::XDown,
+        Position::YUp,
+        Position::YDown,
+    ];
+
+    /// Axis index and the sign of gravity measured along it
+    fn axis(self) -> (usize, f32) {
+        match self {
+            Position::XUp => (0, 1.0),
+            Position::XDown => (0, -1.0),
+            Position::YUp => (1, 1.0),
+            Position::YDown => (1, -1.0),
+            Position::ZUp => (2, 1.0),
+            Position::ZDown => (2, -1.0),
+        }
+    }
+
+    /// LEDs prompting for this position: the LED on the edge that goes up,
+    /// all LEDs for lying flat and none when the LEDs face the table
+    fn prompt(self) -> u8 {
+        match self {
+            Position::ZUp => 0b1111,
+            Position::ZDown => 0,
+            Position::XUp => 1 << LedColor::Red as u8,
+            Position::XDown => 1 << LedColor::Green as u8,
+            Position::YUp => 1 << LedColor::Orange as u8,
+            Position::YDown => 1 << LedColor::Blue as u8,
+        }
+    }
+}
+
+/// Accelerometer bias and gain correction
+///
+/// A reading `a` in g is corrected to `matrix × (a − bias)`. Without
+/// cross-axis terms the matrix is diagonal and holds the per-axis scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AccelCalibration {
+    /// Zero-g offset of each axis, in g
+    pub bias: [f32; 3],
+    /// Scale and cross-axis correction
+    pub matrix: Matrix3,
+}
+
+impl Default for AccelCalibration {
+    fn default() -> Self {
+        AccelCalibration {
+            bias: [0.0; 3],
+            matrix: IDENTITY,
+        }
+    }
+}
+
+impl AccelCalibration {
+    /// Length of the serialized form
+    pub const LEN: usize = 4 + 12 * 4 + 4;
+
+    /// Magic word of the serialized form, "ACC1"
+    const MAGIC: u32 = 0x3143_4341;
+
+    /// Applies the correction to a reading in g
+    pub fn apply(&self, reading: F32x3) -> F32x3 {
+        let a = [
+            reading.x - self.bias[0],
+            reading.y - self.bias[1],
+            reading.z - self.bias[2],
+        ];
+        let [x, y, z] = mul(&self.matrix, a);
+        F32x3::new(x, y, z)
+    }
+
+    /// Serializes the calibration
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        serialize(Self::MAGIC, &pack(&self.bias, &self.matrix), &mut bytes);
+        bytes
+    }
+
+    /// Restores a calibration serialized with [`AccelCalibration::to_bytes`]
+    ///
+    /// Returns `None` if the magic word or checksum do not match.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut values = [0.0; 12];
+        deserialize(Self::MAGIC, bytes, &mut values)?;
+
+        let (bias, matrix) = unpack(&values);
+        Some(AccelCalibration { bias, matrix })
+    }
+}
+
+/// Collects the mean readings of the six positions and solves for the
+/// calibration
+///
+/// This is the non-blocking core of [`calibrate_accelerometer`], for
+/// applications that drive the procedure themselves.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SixPosition {
+    means: [Option<[f32; 3]>; 6],
+}
+
+impl SixPosition {
+    /// Creates an empty set of measurements
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the mean uncalibrated reading, in g, of a position
+    pub fn record(&mut self, position: Position, mean: F32x3) {
+        let index = Position::ALL.iter().position(|&p| p == position).unwrap();
+        self.means[index] = Some([mean.x, mean.y, mean.z]);
+    }
+
+    /// Returns the first position without a measurement
+    pub fn next_position(&self) -> Option<Position> {
+        Position::ALL
+            .iter()
+            .zip(self.means.iter())
+            .find(|(_, mean)| mean.is_none())
+            .map(|(&position, _)| position)
+    }
+
+    /// Computes the calibration once all six positions are recorded
+    ///
+    /// With `cross_axis` set, the full 3×3 matrix is estimated, which also
+    /// corrects axis misalignment. Returns `None` if positions are missing
+    /// or the measurements are degenerate.
+    pub fn solve(&self, cross_axis: bool) -> Option<AccelCalibration> {
+        let mut means = [[0.0; 3]; 6];
+        for (mean, recorded) in means.iter_mut().zip(self.means.iter()) {
+            *mean = (*recorded)?;
+        }
+
+        // Opposite positions see the same bias and opposite gravity
+        let mut bias = [0.0; 3];
+        for mean in means.iter() {
+            for (b, m) in bias.iter_mut().zip(mean.iter()) {
+                *b += m / 6.0;
+            }
+        }
+
+        // Column i is the response to 1 g along axis i
+        let mut response = [[0.0; 3]; 3];
+        for axis in 0..3 {
+            let up = means[index_of(axis, 1.0)];
+            let down = means[index_of(axis, -1.0)];
+            for (row, (u, d)) in response.iter_mut().zip(up.iter().zip(down.iter())) {
+                row[axis] = (u - d) / 2.0;
+            }
+        }
+
+        let matrix = if cross_axis {
+            invert(&response)?
+        } else {
+            let mut matrix = [[0.0; 3]; 3];
+            for (axis, row) in matrix.iter_mut().enumerate() {
+                if response[axis][axis].abs() < f32::EPSILON {
+                    return None;
+                }
+                row[axis] = 1.0 / response[axis][axis];
+            }
+            matrix
+        };
+
+        Some(AccelCalibration { bias, matrix })
+    }
+}
+
+/// Index in `Position::ALL` of the position with gravity `sign` on `axis`
+fn index_of(axis: usize, sign: f32) -> usize {
+    Position::ALL
+        .iter()
+        .position(|p| p.axis() == (axis, sign))
+        .unwrap()
+}
+
+/// Runs the six-position calibration, prompting for each position on the LEDs
+///
+/// For every position, the LEDs show which edge to turn up (see
+/// [`Position`]) and the routine waits until the board is held still in it,
+/// then averages a burst of samples and blinks all LEDs. The calibration in
+/// use is bypassed while measuring and left unchanged; apply the result with
+/// [`Accelerometer::set_calibration`]. Returns `None` if the measurements are
+/// degenerate and `Error::Timeout` if the board is not held still in a
+/// position within about 30 s.
+pub fn calibrate_accelerometer<I2C, E, D>(
+    accelerometer: &mut Accelerometer<I2C>,
+    leds: &mut Leds,
+    delay: &mut D,
+    cross_axis: bool,
+) -> Result<Option<AccelCalibration>, Error<E>>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+    D: DelayMs<u16>,
+{
+    let previous = accelerometer.calibration();
+    accelerometer.set_calibration(None);
+
+    let mut six = SixPosition::new();
+    let result = loop {
+        let position = match six.next_position() {
+            Some(position) => position,
+            None => break Ok(six.solve(cross_axis)),
+        };
+
+        leds.set_mask(position.prompt());
+        match measure_still(accelerometer, delay, position) {
+            Ok(mean) => six.record(position, mean),
+            Err(error) => break Err(error),
+        }
+
+        for _ in 0..3 {
+            leds.set_mask(0b1111);
+            delay.delay_ms(100);
+            leds.set_mask(0);
+            delay.delay_ms(100);
+        }
+    };
+
+    accelerometer.set_calibration(previous);
+    result
+}
+
+/// Waits until a full burst is taken with the board still in `position` and
+/// returns its mean, giving up after [`ATTEMPTS`] bursts
+fn measure_still<I2C, E, D>(
+    accelerometer: &mut Accelerometer<I2C>,
+    delay: &mut D,
+    position: Position,
+) -> Result<F32x3, Error<E>>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+    D: DelayMs<u16>,
+{
+    let (axis, sign) = position.axis();
+    let mut samples = [[0.0f32; 3]; SAMPLES];
+
+    for _ in 0..ATTEMPTS {
+        for sample in samples.iter_mut() {
+            let raw = accelerometer.read_sample()?;
+            let a = accelerometer.to_g(raw);
+            *sample = [a.x, a.y, a.z];
+            delay.delay_ms(SAMPLE_PERIOD_MS);
+        }
+
+        let mut mean = [0.0; 3];
+        for sample in samples.iter() {
+            for (m, s) in mean.iter_mut().zip(sample.iter()) {
+                *m += s / SAMPLES as f32;
+            }
+        }
+
+        let still = samples.iter().all(|sample| {
+            sample
+                .iter()
+                .zip(mean.iter())
+                .all(|(s, m)| (s - m).abs() < STILL_G)
+        });
+
+        if still && mean[axis] * sign > ALIGNED_G {
+            return Ok(F32x3::new(mean[0], mean[1], mean[2]));
+        }
+    }
+
+    Err(Error::Timeout(position))
+}
+
+/// Magnetometer hard-iron and soft-iron correction
+///
+/// A reading `m` in gauss is corrected to `matrix × (m − offset)`, which maps
+/// the measured ellipsoid back onto a sphere with the radius of the local
+/// field.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MagCalibration {
+    /// Hard-iron offset, the center of the ellipsoid, in gauss
+    pub offset: [f32; 3],
+    /// Soft-iron correction
+    pub matrix: Matrix3,
+}
+
+impl Default for MagCalibration {
+    fn default() -> Self {
+        MagCalibration {
+            offset: [0.0; 3],
+            matrix: IDENTITY,
+        }
+    }
+}
+
+impl MagCalibration {
+    /// Length of the serialized form
+    pub const LEN: usize = 4 + 12 * 4 + 4;
+
+    /// Magic word of the serialized form, "MAG1"
+    const MAGIC: u32 = 0x3147_414D;
+
+    /// Applies the correction to a reading in gauss
+    pub fn apply(&self, reading: F32x3) -> F32x3 {
+        let m = [
+            reading.x - self.offset[0],
+            reading.y - self.offset[1],
+            reading.z - self.offset[2],
+        ];
+        let [x, y, z] = mul(&self.matrix, m);
+        F32x3::new(x, y, z)
+    }
+
+    /// Serializes the calibration
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        serialize(Self::MAGIC, &pack(&self.offset, &self.matrix), &mut bytes);
+        bytes
+    }
+
+    /// Restores a calibration serialized with [`MagCalibration::to_bytes`]
+    ///
+    /// Returns `None` if the magic word or checksum do not match.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut values = [0.0; 12];
+        deserialize(Self::MAGIC, bytes, &mut values)?;
+
+        let (offset, matrix) = unpack(&values);
+        Some(MagCalibration { offset, matrix })
+    }
+}
+
+/// How well an ellipsoid fit matches the samples
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FitQuality {
+    /// Number of samples used
+    pub samples: u32,
+    /// Fraction of the 26 directions around the center that were visited,
+    /// from 0 to 1
+    pub coverage: f32,
+    /// Approximate RMS deviation of the samples from the ellipsoid, relative
+    /// to its radius
+    pub residual: f32,
+    /// Strength of the local field, in gauss
+    pub field: f32,
+}
+
+/// Number of coarse directions `EllipsoidFit` tracks for the coverage
+const DIRECTIONS: usize = 26;
+
+/// Collects magnetometer samples while the board is turned around and fits
+/// an ellipsoid to them
+///
+/// Samples are folded into the normal equations of the least squares fit as
+/// they arrive, so no sample buffer is needed. The fit constrains the trace
+/// of the quadratic form rather than the constant term, so it does not
+/// depend on where the origin lies, even when the hard-iron offset exceeds
+/// the field strength. Feed readings in gauss
+/// without any calibration applied, taken in as many orientations as
+/// possible.
+#[derive(Clone)]
+pub struct EllipsoidFit {
+    normal: [[f64; 9]; 9],
+    rhs: [f64; 9],
+    target: f64,
+    samples: u32,
+    min: [f32; 3],
+    max: [f32; 3],
+    visited: u32,
+}
+
+impl Default for EllipsoidFit {
+    fn default() -> Self {
+        EllipsoidFit {
+            normal: [[0.0; 9]; 9],
+            rhs: [0.0; 9],
+            target: 0.0,
+            samples: 0,
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            visited: 0,
+        }
+    }
+}
+
+impl EllipsoidFit {
+    /// Creates an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reading in gauss
+    pub fn add(&mut self, reading: F32x3) {
+        let m = [reading.x, reading.y, reading.z];
+
+        // Coverage is judged around the center of the bounding box so far
+        for ((min, max), &value) in self.min.iter_mut().zip(self.max.iter_mut()).zip(m.iter()) {
+            *min = min.min(value);
+            *max = max.max(value);
+        }
+        if let Some(direction) = self.direction(&m) {
+            self.visited |= 1 << direction;
+        }
+
+        let [x, y, z] = [f64::from(m[0]), f64::from(m[1]), f64::from(m[2])];
+        let row = [
+            x * x - z * z,
+            y * y - z * z,
+            2.0 * x * y,
+            2.0 * x * z,
+            2.0 * y * z,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+            1.0,
+        ];
+        let target = -z * z;
+        for (i, ri) in row.iter().enumerate() {
+            for (j, rj) in row.iter().enumerate() {
+                self.normal[i][j] += ri * rj;
+            }
+            self.rhs[i] += ri * target;
+        }
+        self.target += target * target;
+        self.samples += 1;
+    }
+
+    /// Number of readings added so far
+    pub fn len(&self) -> u32 {
+        self.samples
+    }
+
+    /// Returns `true` before the first reading
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Fraction of the 26 coarse directions visited so far, from 0 to 1
+    pub fn coverage(&self) -> f32 {
+        self.visited.count_ones() as f32 / DIRECTIONS as f32
+    }
+
+    /// Fits the ellipsoid
+    ///
+    /// Returns `None` with fewer than 9 readings or if the readings do not
+    /// describe an ellipsoid, e.g. because the board was only turned around
+    /// one axis.
+    pub fn solve(&self) -> Option<(MagCalibration, FitQuality)> {
+        if self.samples < 9 {
+            return None;
+        }
+
+        // a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z
+        // + j = 0 with a + b + c = 1
+        let p = solve9(self.normal, self.rhs)?;
+        let c = 1.0 - p[0] - p[1];
+        let q = [[p[0], p[2], p[3]], [p[2], p[1], p[4]], [p[3], p[4], c]];
+        let v = [p[5], p[6], p[7]];
+
+        // Center where the gradient vanishes: Q c = -v
+        let center = solve3(q, [-v[0], -v[1], -v[2]])?;
+
+        // (m - c)ᵀ Q (m - c) = cᵀ Q c - j
+        let mut k = -p[8];
+        for i in 0..3 {
+            for j in 0..3 {
+                k += center[i] * q[i][j] * center[j];
+            }
+        }
+        if k <= 0.0 {
+            return None;
+        }
+
+        let (values, vectors) = eigen3(q);
+        let mut radii = [0.0; 3];
+        for (radius, value) in radii.iter_mut().zip(values.iter()) {
+            if *value <= 0.0 {
+                return None;
+            }
+            *radius = libm::sqrt(k / value);
+        }
+        let field = libm::cbrt(radii[0] * radii[1] * radii[2]);
+
+        // Scale every principal axis to the mean radius: V diag(field / r) Vᵀ
+        let mut matrix = [[0.0f32; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, m) in row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for (axis, radius) in radii.iter().enumerate() {
+                    sum += vectors[i][axis] * (field / radius) * vectors[j][axis];
+                }
+                *m = sum as f32;
+            }
+        }
+
+        // Sum of squared residuals pᵀ N p - 2 pᵀ r + Σ z⁴ of the equation
+        // above. A relative radial error ε changes its left side by about
+        // 2 k ε.
+        let mut residual = self.target;
+        for i in 0..9 {
+            residual -= 2.0 * p[i] * self.rhs[i];
+            for j in 0..9 {
+                residual += p[i] * self.normal[i][j] * p[j];
+            }
+        }
+        let residual = libm::sqrt(residual.max(0.0) / f64::from(self.samples)) / (2.0 * k);
+
+        let calibration = MagCalibration {
+            offset: [center[0] as f32, center[1] as f32, center[2] as f32],
+            matrix,
+        };
+        let quality = FitQuality {
+            samples: self.samples,
+            coverage: self.coverage(),
+            residual: residual as f32,
+            field: field as f32,
+        };
+        Some((calibration, quality))
+    }
+
+    /// Coarse direction of a reading seen from the bounding box center
+    ///
+    /// Each component counts as positive, negative or zero, giving the 26
+    /// faces, edges and corners of a cube.
+    fn direction(&self, m: &[f32; 3]) -> Option<usize> {
+        let mut d = [0.0; 3];
+        let mut norm = 0.0;
+        for i in 0..3 {
+            d[i] = m[i] - (self.min[i] + self.max[i]) / 2.0;
+            norm += d[i] * d[i];
+        }
+        let norm = libm::sqrtf(norm);
+        if norm == 0.0 {
+            return None;
+        }
+
+        let mut index = 0;
+        for di in d.iter() {
+            let sign = if di / norm > 0.5 {
+                2
+            } else if di / norm < -0.5 {
+                0
+            } else {
+                1
+            };
+            index = index * 3 + sign;
+        }
+
+        // Skip the center cell (1, 1, 1)
+        match index {
+            13 => None,
+            i if i > 13 => Some(i - 1),
+            i => Some(i),
+        }
+    }
+}
+
+/// Gyroscope zero-rate level and its temperature drift
+///
+/// Temperatures are raw OUT_TEMP readings, see
+/// [`Gyroscope::temperature`](crate::gyroscope::Gyroscope::temperature).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GyroCalibration {
+    /// Zero-rate level at `reference`, in dps
+    pub bias: [f32; 3],
+    /// Change of the zero-rate level per OUT_TEMP LSB, in dps, zero when
+    /// not compensating the temperature
+    pub temperature_coefficient: [f32; 3],
+    /// OUT_TEMP reading at which `bias` was measured
+    pub reference: f32,
+}
+
+impl GyroCalibration {
+    /// Length of the serialized form
+    pub const LEN: usize = 4 + 7 * 4 + 4;
+
+    /// Magic word of the serialized form, "GYR1"
+    const MAGIC: u32 = 0x3152_5947;
+
+    /// Zero-rate level at the given OUT_TEMP reading, in dps
+    pub fn bias_at(&self, temperature: i8) -> [f32; 3] {
+        let delta = f32::from(temperature) - self.reference;
+        let mut bias = self.bias;
+        for (b, c) in bias.iter_mut().zip(self.temperature_coefficient.iter()) {
+            *b += c * delta;
+        }
+        bias
+    }
+
+    /// Removes the zero-rate level from a reading in dps
+    pub fn apply(&self, reading: F32x3, temperature: i8) -> F32x3 {
+        let [x, y, z] = self.bias_at(temperature);
+        F32x3::new(reading.x - x, reading.y - y, reading.z - z)
+    }
+
+    /// Serializes the calibration
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut values = [0.0; 7];
+        values[..3].copy_from_slice(&self.bias);
+        values[3..6].copy_from_slice(&self.temperature_coefficient);
+        values[6] = self.reference;
+
+        let mut bytes = [0u8; Self::LEN];
+        serialize(Self::MAGIC, &values, &mut bytes);
+        bytes
+    }
+
+    /// Restores a calibration serialized with [`GyroCalibration::to_bytes`]
+    ///
+    /// Returns `None` if the magic word or checksum do not match.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut values = [0.0; 7];
+        deserialize(Self::MAGIC, bytes, &mut values)?;
+
+        let mut calibration = GyroCalibration {
+            reference: values[6],
+            ..GyroCalibration::default()
+        };
+        calibration.bias.copy_from_slice(&values[..3]);
+        calibration
+            .temperature_coefficient
+            .copy_from_slice(&values[3..6]);
+        Some(calibration)
+    }
+}
+
+/// Minimum spread of the still windows, in OUT_TEMP LSB, before a
+/// temperature coefficient is fitted
+const TEMPERATURE_SPAN: f64 = 2.0;
+
+/// Estimates the gyroscope zero-rate level whenever the board is at rest
+///
+/// Readings are grouped into windows. A window whose readings all stay
+/// within `threshold_dps` of their mean on every axis counts as still, and
+/// its mean becomes the new zero-rate level. The means of all still windows
+/// are also regressed against temperature, which yields the temperature
+/// coefficient once they span a few degrees.
+///
+/// A perfectly steady rotation looks like rest, so windows whose mean
+/// differs from the current zero-rate level by more than `max_change_dps`
+/// on any axis are rejected as well. Keep the windows long compared to any
+/// motion the board may see.
+#[derive(Clone)]
+pub struct BiasEstimator {
+    threshold_dps: f32,
+    window: u16,
+    max_change_dps: f32,
+    count: u16,
+    sum: [f32; 3],
+    min: [f32; 3],
+    max: [f32; 3],
+    temperature: i32,
+    fit_n: f64,
+    fit_t: f64,
+    fit_tt: f64,
+    fit_b: [f64; 3],
+    fit_tb: [f64; 3],
+    calibration: GyroCalibration,
+}
+
+impl BiasEstimator {
+    /// Creates an estimator with windows of `window` readings, starting
+    /// from `initial`
+    ///
+    /// The first window is also checked against `initial`, so without a
+    /// prior calibration `max_change_dps` has to cover the zero-rate level
+    /// of the part, up to ±10 dps on the L3GD20.
+    pub fn new(
+        threshold_dps: f32,
+        window: u16,
+        max_change_dps: f32,
+        initial: GyroCalibration,
+    ) -> Self {
+        BiasEstimator {
+            threshold_dps,
+            window: window.max(1),
+            max_change_dps,
+            count: 0,
+            sum: [0.0; 3],
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            temperature: 0,
+            fit_n: 0.0,
+            fit_t: 0.0,
+            fit_tt: 0.0,
+            fit_b: [0.0; 3],
+            fit_tb: [0.0; 3],
+            calibration: initial,
+        }
+    }
+
+    /// Feeds a reading in dps, without any calibration applied, and the
+    /// OUT_TEMP reading taken with it
+    ///
+    /// Returns the updated calibration at the end of a still window.
+    pub fn update(&mut self, reading: F32x3, temperature: i8) -> Option<GyroCalibration> {
+        for (i, &r) in [reading.x, reading.y, reading.z].iter().enumerate() {
+            self.sum[i] += r;
+            self.min[i] = self.min[i].min(r);
+            self.max[i] = self.max[i].max(r);
+        }
+        self.temperature += i32::from(temperature);
+        self.count += 1;
+        if self.count < self.window {
+            return None;
+        }
+
+        let n = f32::from(self.count);
+        let mean = [self.sum[0] / n, self.sum[1] / n, self.sum[2] / n];
+        let still = (0..3).all(|i| {
+            self.max[i] - mean[i] <= self.threshold_dps
+                && mean[i] - self.min[i] <= self.threshold_dps
+        });
+        let temperature = self.temperature as f32 / n;
+
+        self.count = 0;
+        self.sum = [0.0; 3];
+        self.min = [f32::MAX; 3];
+        self.max = [f32::MIN; 3];
+        self.temperature = 0;
+
+        if !still {
+            return None;
+        }
+
+        // Zero-rate level expected at the window temperature
+        let delta = temperature - self.calibration.reference;
+        let steady = mean
+            .iter()
+            .zip(self.calibration.bias.iter())
+            .zip(self.calibration.temperature_coefficient.iter())
+            .all(|((m, b), c)| (m - (b + c * delta)).abs() <= self.max_change_dps);
+        if !steady {
+            return None;
+        }
+
+        let t = f64::from(temperature);
+        self.fit_n += 1.0;
+        self.fit_t += t;
+        self.fit_tt += t * t;
+        for ((sum_b, sum_tb), &m) in self
+            .fit_b
+            .iter_mut()
+            .zip(self.fit_tb.iter_mut())
+            .zip(mean.iter())
+        {
+            let b = f64::from(m);
+            *sum_b += b;
+            *sum_tb += t * b;
+        }
+
+        // Variance of the window temperatures, times the window count
+        let spread = self.fit_tt - self.fit_t * self.fit_t / self.fit_n;
+        if spread >= TEMPERATURE_SPAN * TEMPERATURE_SPAN * self.fit_n / 4.0 {
+            for i in 0..3 {
+                let covariance = self.fit_tb[i] - self.fit_t * self.fit_b[i] / self.fit_n;
+                self.calibration.temperature_coefficient[i] = (covariance / spread) as f32;
+            }
+        }
+        self.calibration.bias = mean;
+        self.calibration.reference = temperature;
+
+        Some(self.calibration)
+    }
+
+    /// Returns the current calibration
+    pub fn calibration(&self) -> GyroCalibration {
+        self.calibration
+    }
+}
+
+/// Solves the 9×9 system `a x = b` by Gaussian elimination with partial
+/// pivoting, `None` if it is singular
+fn solve9(mut a: [[f64; 9]; 9], mut b: [f64; 9]) -> Option<[f64; 9]> {
+    for col in 0..9 {
+        let pivot = (col..9).max_by(|&i, &j| {
+            a[i][col]
+                .abs()
+                .partial_cmp(&a[j][col].abs())
+                .unwrap_or(core::cmp::Ordering::Equal)
+        })?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col];
+        for row in col + 1..9 {
+            let factor = a[row][col] / pivot_row[col];
+            for (x, p) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; 9];
+    for row in (0..9).rev() {
+        let mut sum = b[row];
+        for k in row + 1..9 {
+            sum -= a[row][k] * x[k];
+        }
+        x[row] = sum / a[row][row];
+    }
+    Some(x)
+}
+
+/// Solves the 3×3 system `a x = b` with Cramer's rule, `None` if it is
+/// singular
+fn solve3(a: [[f64; 3]; 3], b: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+
+    let d = det(&a);
+    if d.abs() < 1e-12 {
+        return None;
+    }
+
+    let mut x = [0.0; 3];
+    for (col, xi) in x.iter_mut().enumerate() {
+        let mut m = a;
+        for (row, bi) in b.iter().enumerate() {
+            m[row][col] = *bi;
+        }
+        *xi = det(&m) / d;
+    }
+    Some(x)
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric 3×3 matrix, by
+/// cyclic Jacobi rotations
+fn eigen3(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..32 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off < 1e-24 {
+            break;
+        }
+
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + libm::sqrt(theta * theta + 1.0));
+            let c = 1.0 / libm::sqrt(t * t + 1.0);
+            let s = t * c;
+
+            // a = Jᵀ a J and v = v J with J the rotation in the (p, q) plane
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            for (k, (apk, aqk)) in row_p.iter().zip(row_q.iter()).enumerate() {
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// Flattens a vector and a matrix into the serialized value order
+fn pack(vector: &[f32; 3], matrix: &Matrix3) -> [f32; 12] {
+    let mut values = [0.0; 12];
+    values[..3].copy_from_slice(vector);
+    for (row, chunk) in matrix.iter().zip(values[3..].chunks_mut(3)) {
+        chunk.copy_from_slice(row);
+    }
+    values
+}
+
+/// Reverse of `pack`
+fn unpack(values: &[f32; 12]) -> ([f32; 3], Matrix3) {
+    let mut vector = [0.0; 3];
+    let mut matrix = [[0.0; 3]; 3];
+    vector.copy_from_slice(&values[..3]);
+    for (row, chunk) in matrix.iter_mut().zip(values[3..].chunks(3)) {
+        row.copy_from_slice(chunk);
+    }
+    (vector, matrix)
+}
+
+/// Matrix-vector product
+fn mul(m: &Matrix3, v: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (o, row) in out.iter_mut().zip(m.iter()) {
+        *o = row[0] * v[0] + row[1] * v[1] + row[2] * v[2];
+    }
+    out
+}
+
+/// Inverse of a 3×3 matrix, `None` if it is singular
+fn invert(m: &Matrix3) -> Option<Matrix3> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+    let c00 = cofactor(1, 2, 1, 2);
+    let c01 = -cofactor(1, 2, 0, 2);
+    let c02 = cofactor(1, 2, 0, 1);
+    let det = m[0][0] * c00 + m[0][1] * c01 + m[0][2] * c02;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    // Adjugate (transposed cofactors) over the determinant
+    Some([
+        [
+            c00 / det,
+            -cofactor(0, 2, 1, 2) / det,
+            cofactor(0, 1, 1, 2) / det,
+        ],
+        [
+            c01 / det,
+            cofactor(0, 2, 0, 2) / det,
+            -cofactor(0, 1, 0, 2) / det,
+        ],
+        [
+            c02 / det,
+            -cofactor(0, 2, 0, 1) / det,
+            cofactor(0, 1, 0, 1) / det,
+        ],
+    ])
+}
+
+/// Writes the magic word, the values and a checksum, all little endian
+fn serialize(magic: u32, values: &[f32], bytes: &mut [u8]) {
+    bytes[..4].copy_from_slice(&magic.to_le_bytes());
+    for (chunk, value) in bytes[4..].chunks_mut(4).zip(values.iter()) {
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+
+    let end = 4 + 4 * values.len();
+    let sum = checksum(&bytes[..end]);
+    bytes[end..end + 4].copy_from_slice(&sum.to_le_bytes());
+}
+
+/// Reads back what `serialize` wrote, `None` if the frame does not match
+fn deserialize(magic: u32, bytes: &[u8], values: &mut [f32]) -> Option<()> {
+    let end = 4 + 4 * values.len();
+    if bytes.len() < end + 4 {
+        return None;
+    }
+
+    let word =
+        |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    if word(0) != magic || word(end) != checksum(&bytes[..end]) {
+        return None;
+    }
+
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = f32::from_bits(word(4 + 4 * i));
+    }
+    Some(())
+}
+
+/// Fletcher-32 checksum over bytes
+fn checksum(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65535;
+        b = (b + a) % 65535;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    /// Sensor with a known bias and response, read in the six positions
+    fn six_positions(bias: [f32; 3], response: &Matrix3) -> SixPosition {
+        let mut six = SixPosition::new();
+        for &position in Position::ALL.iter() {
+            let (axis, sign) = position.axis();
+            let mut gravity = [0.0; 3];
+            gravity[axis] = sign;
+            let r = mul(response, gravity);
+            six.record(
+                position,
+                F32x3::new(r[0] + bias[0], r[1] + bias[1], r[2] + bias[2]),
+            );
+        }
+        six
+    }
+
+    #[test]
+    fn six_position_recovers_bias_and_cross_axis() {
+        let bias = [0.05, -0.03, 0.1];
+        let response = [
+            [1.04, 0.02, -0.01],
+            [0.03, 0.97, 0.015],
+            [-0.02, 0.01, 1.08],
+        ];
+        let six = six_positions(bias, &response);
+        assert_eq!(six.next_position(), None);
+
+        let calibration = six.solve(true).unwrap();
+        let expected = invert(&response).unwrap();
+        for i in 0..3 {
+            assert_close(calibration.bias[i], bias[i], 1e-6);
+            for (&actual, &expected) in calibration.matrix[i].iter().zip(expected[i].iter()) {
+                assert_close(actual, expected, 1e-5);
+            }
+        }
+
+        // Every position reads exactly 1 g along its axis once corrected
+        for &position in Position::ALL.iter() {
+            let (axis, sign) = position.axis();
+            let mut gravity = [0.0; 3];
+            gravity[axis] = sign;
+            let r = mul(&response, gravity);
+            let a = calibration.apply(F32x3::new(r[0] + bias[0], r[1] + bias[1], r[2] + bias[2]));
+            let a = [a.x, a.y, a.z];
+            for (i, &g) in gravity.iter().enumerate() {
+                assert_close(a[i], g, 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn six_position_scale_only() {
+        let bias = [-0.02, 0.04, 0.01];
+        let response = [[0.95, 0.0, 0.0], [0.0, 1.05, 0.0], [0.0, 0.0, 1.1]];
+        let calibration = six_positions(bias, &response).solve(false).unwrap();
+
+        for i in 0..3 {
+            assert_close(calibration.bias[i], bias[i], 1e-6);
+            for j in 0..3 {
+                let expected = if i == j { 1.0 / response[i][i] } else { 0.0 };
+                assert_close(calibration.matrix[i][j], expected, 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn six_position_needs_all_positions() {
+        let mut six = SixPosition::new();
+        assert_eq!(six.next_position(), Some(Position::ZUp));
+        six.record(Position::ZUp, F32x3::new(0.0, 0.0, 1.0));
+        assert_eq!(six.next_position(), Some(Position::ZDown));
+        assert_eq!(six.solve(true), None);
+    }
+
+    #[test]
+    fn six_position_rejects_degenerate_measurements() {
+        // The board never left the table
+        let mut six = SixPosition::new();
+        for &position in Position::ALL.iter() {
+            six.record(position, F32x3::new(0.0, 0.0, 1.0));
+        }
+        assert_eq!(six.solve(true), None);
+        assert_eq!(six.solve(false), None);
+    }
+
+    #[test]
+    fn accel_calibration_round_trip() {
+        let calibration = AccelCalibration {
+            bias: [0.05, -0.03, 0.1],
+            matrix: [
+                [0.96, -0.02, 0.01],
+                [-0.03, 1.03, -0.015],
+                [0.02, -0.01, 0.93],
+            ],
+        };
+        let bytes = calibration.to_bytes();
+        assert_eq!(&bytes[..4], b"ACC1");
+        assert_eq!(AccelCalibration::from_bytes(&bytes), Some(calibration));
+    }
+
+    #[test]
+    fn accel_calibration_rejects_corrupted_frames() {
+        let bytes = AccelCalibration::default().to_bytes();
+
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes;
+            corrupted[i] ^= 0x10;
+            assert_eq!(AccelCalibration::from_bytes(&corrupted), None, "byte {}", i);
+        }
+
+        // Erased flash, truncated frame and another calibration's frame
+        assert_eq!(
+            AccelCalibration::from_bytes(&[0xFF; AccelCalibration::LEN]),
+            None
+        );
+        assert_eq!(
+            AccelCalibration::from_bytes(&bytes[..bytes.len() - 1]),
+            None
+        );
+        let mag = MagCalibration::default().to_bytes();
+        assert_eq!(AccelCalibration::from_bytes(&mag), None);
+    }
+
+    /// Field directions spread evenly over the sphere, visited in a
+    /// scrambled order like a board turned by hand
+    fn sphere(n: usize) -> impl Iterator<Item = [f32; 3]> {
+        let golden = core::f32::consts::PI * (3.0 - libm::sqrtf(5.0));
+        (0..n).map(move |j| {
+            let i = j * 7919 % n;
+            let z = 1.0 - 2.0 * (i as f32 + 0.5) / n as f32;
+            let r = libm::sqrtf(1.0 - z * z);
+            let phi = golden * i as f32;
+            [r * libm::cosf(phi), r * libm::sinf(phi), z]
+        })
+    }
+
+    /// Soft-iron distortion, symmetric positive definite, and its inverse
+    /// scaled to unit determinant
+    const SOFT_IRON: Matrix3 = [[1.2, 0.1, -0.05], [0.1, 0.9, 0.08], [-0.05, 0.08, 1.05]];
+    const HARD_IRON: [f32; 3] = [0.21, -0.13, 0.37];
+    const FIELD: f32 = 0.48;
+
+    /// Reading of a field of strength `FIELD` along `direction`, scaled by
+    /// `gain`, through the distortion
+    fn distorted(direction: [f32; 3], gain: f32) -> F32x3 {
+        let h = [
+            direction[0] * FIELD * gain,
+            direction[1] * FIELD * gain,
+            direction[2] * FIELD * gain,
+        ];
+        let m = mul(&SOFT_IRON, h);
+        F32x3::new(
+            m[0] + HARD_IRON[0],
+            m[1] + HARD_IRON[1],
+            m[2] + HARD_IRON[2],
+        )
+    }
+
+    fn determinant(m: &Matrix3) -> f32 {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    #[test]
+    fn ellipsoid_fit_recovers_hard_and_soft_iron() {
+        let mut fit = EllipsoidFit::new();
+        for direction in sphere(200) {
+            fit.add(distorted(direction, 1.0));
+        }
+        assert_eq!(fit.len(), 200);
+        assert!(fit.coverage() > 0.8, "coverage {}", fit.coverage());
+
+        let (calibration, quality) = fit.solve().unwrap();
+
+        // The fit scales every axis to the geometric mean radius, so it
+        // recovers the inverse distortion up to that scale
+        let scale = libm::cbrtf(determinant(&SOFT_IRON));
+        let inverse = invert(&SOFT_IRON).unwrap();
+        for i in 0..3 {
+            assert_close(calibration.offset[i], HARD_IRON[i], 1e-4);
+            for (&actual, &expected) in calibration.matrix[i].iter().zip(inverse[i].iter()) {
+                assert_close(actual, expected * scale, 1e-4);
+            }
+        }
+        assert_close(quality.field, FIELD * scale, 1e-4);
+        assert!(quality.residual < 1e-4, "residual {}", quality.residual);
+        assert_eq!(quality.samples, 200);
+
+        // Corrected readings lie on a sphere of the reported field strength
+        for direction in sphere(50) {
+            let m = calibration.apply(distorted(direction, 1.0));
+            let norm = libm::sqrtf(m.x * m.x + m.y * m.y + m.z * m.z);
+            assert_close(norm, quality.field, 1e-3);
+        }
+    }
+
+    #[test]
+    fn ellipsoid_fit_reports_radial_noise_as_residual() {
+        // Radial errors of ±1 %, so the RMS relative error is 1 %
+        let mut fit = EllipsoidFit::new();
+        for (i, direction) in sphere(400).enumerate() {
+            let gain = if i % 2 == 0 { 1.01 } else { 0.99 };
+            fit.add(distorted(direction, gain));
+        }
+
+        let (calibration, quality) = fit.solve().unwrap();
+        assert_close(quality.residual, 0.01, 0.002);
+        for (&actual, &expected) in calibration.offset.iter().zip(HARD_IRON.iter()) {
+            assert_close(actual, expected, 2e-3);
+        }
+    }
+
+    #[test]
+    fn ellipsoid_fit_handles_offsets_beyond_the_field() {
+        // The origin lies well outside the ellipsoid
+        let shift = [0.9, -0.6, 1.2];
+        let mut fit = EllipsoidFit::new();
+        for (i, direction) in sphere(400).enumerate() {
+            let gain = if i % 2 == 0 { 1.01 } else { 0.99 };
+            let m = distorted(direction, gain);
+            fit.add(F32x3::new(m.x + shift[0], m.y + shift[1], m.z + shift[2]));
+        }
+
+        let (calibration, quality) = fit.solve().unwrap();
+        assert_close(quality.residual, 0.01, 0.002);
+        for (i, s) in shift.iter().enumerate() {
+            assert_close(calibration.offset[i], HARD_IRON[i] + s, 2e-3);
+        }
+    }
+
+    #[test]
+    fn ellipsoid_fit_rejects_too_few_or_degenerate_readings() {
+        let mut fit = EllipsoidFit::new();
+        assert!(fit.is_empty());
+        assert!(fit.solve().is_none());
+
+        for direction in sphere(200).take(8) {
+            fit.add(distorted(direction, 1.0));
+        }
+        assert!(fit.solve().is_none());
+
+        // Turned around the z axis only
+        let mut fit = EllipsoidFit::new();
+        for i in 0..100 {
+            let angle = i as f32 * 2.0 * core::f32::consts::PI / 100.0;
+            fit.add(distorted([libm::cosf(angle), libm::sinf(angle), 0.0], 1.0));
+        }
+        assert!(fit.solve().is_none());
+        assert!(fit.coverage() < 0.5, "coverage {}", fit.coverage());
+
+        // The same reading over and over
+        let mut fit = EllipsoidFit::new();
+        for _ in 0..50 {
+            fit.add(distorted([0.0, 0.0, 1.0], 1.0));
+        }
+        assert!(fit.solve().is_none());
+    }
+
+    #[test]
+    fn mag_calibration_round_trip() {
+        let calibration = MagCalibration {
+            offset: HARD_IRON,
+            matrix: invert(&SOFT_IRON).unwrap(),
+        };
+        let bytes = calibration.to_bytes();
+        assert_eq!(&bytes[..4], b"MAG1");
+        assert_eq!(MagCalibration::from_bytes(&bytes), Some(calibration));
+    }
+
+    #[test]
+    fn mag_calibration_rejects_corrupted_frames() {
+        let bytes = MagCalibration {
+            offset: HARD_IRON,
+            matrix: SOFT_IRON,
+        }
+        .to_bytes();
+
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes;
+            corrupted[i] ^= 0x01;
+            assert_eq!(MagCalibration::from_bytes(&corrupted), None, "byte {}", i);
+        }
+
+        assert_eq!(
+            MagCalibration::from_bytes(&[0xFF; MagCalibration::LEN]),
+            None
+        );
+        assert_eq!(MagCalibration::from_bytes(&bytes[..8]), None);
+        let accel = AccelCalibration::default().to_bytes();
+        assert_eq!(MagCalibration::from_bytes(&accel), None);
+    }
+
+    /// Feeds one window of readings around `rate` with a deterministic
+    /// ±`noise` on every axis, returning the last result
+    fn feed_window(
+        estimator: &mut BiasEstimator,
+        window: u16,
+        rate: [f32; 3],
+        noise: f32,
+        temperature: i8,
+    ) -> Option<GyroCalibration> {
+        let mut result = None;
+        for i in 0..window {
+            let n = if i % 2 == 0 { noise } else { -noise };
+            let reading = F32x3::new(rate[0] + n, rate[1] - n, rate[2] + n);
+            result = estimator.update(reading, temperature);
+            if i + 1 < window {
+                assert_eq!(result, None);
+            }
+        }
+        result
+    }
+
+    const BIAS: [f32; 3] = [1.5, -0.8, 0.3];
+    const DRIFT: [f32; 3] = [0.02, -0.05, 0.01];
+    const REFERENCE: i8 = 20;
+
+    fn bias_at(temperature: i8) -> [f32; 3] {
+        let delta = f32::from(temperature - REFERENCE);
+        [
+            BIAS[0] + DRIFT[0] * delta,
+            BIAS[1] + DRIFT[1] * delta,
+            BIAS[2] + DRIFT[2] * delta,
+        ]
+    }
+
+    #[test]
+    fn bias_estimator_recovers_bias_and_drift() {
+        let mut estimator = BiasEstimator::new(0.2, 50, 5.0, GyroCalibration::default());
+
+        let first = feed_window(&mut estimator, 50, bias_at(REFERENCE), 0.05, REFERENCE).unwrap();
+        for (&actual, &expected) in first.bias.iter().zip(BIAS.iter()) {
+            assert_close(actual, expected, 1e-4);
+        }
+        assert_eq!(first.temperature_coefficient, [0.0; 3]);
+
+        for temperature in REFERENCE + 1..REFERENCE + 7 {
+            let calibration =
+                feed_window(&mut estimator, 50, bias_at(temperature), 0.05, temperature).unwrap();
+            assert_close(calibration.reference, f32::from(temperature), 1e-6);
+        }
+
+        let calibration = estimator.calibration();
+        for (&actual, &expected) in calibration.temperature_coefficient.iter().zip(DRIFT.iter()) {
+            assert_close(actual, expected, 1e-4);
+        }
+        for &temperature in &[10, REFERENCE, 35] {
+            let expected = bias_at(temperature);
+            for (&actual, &expected) in calibration.bias_at(temperature).iter().zip(expected.iter())
+            {
+                assert_close(actual, expected, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn bias_estimator_rejects_noisy_or_moving_windows() {
+        let mut estimator = BiasEstimator::new(0.2, 50, 5.0, GyroCalibration::default());
+        let initial = feed_window(&mut estimator, 50, BIAS, 0.05, REFERENCE).unwrap();
+
+        // Noise above the threshold
+        assert_eq!(feed_window(&mut estimator, 50, BIAS, 0.5, REFERENCE), None);
+
+        // A single bump in an otherwise quiet window
+        for i in 0..50 {
+            let bump = if i == 25 { 2.0 } else { 0.0 };
+            let reading = F32x3::new(BIAS[0] + bump, BIAS[1], BIAS[2]);
+            assert_eq!(estimator.update(reading, REFERENCE), None);
+        }
+
+        // Turning faster and faster
+        for i in 0..50 {
+            let rate = BIAS[2] + i as f32 * 0.1;
+            let reading = F32x3::new(BIAS[0], BIAS[1], rate);
+            assert_eq!(estimator.update(reading, REFERENCE), None);
+        }
+
+        assert_eq!(estimator.calibration(), initial);
+    }
+
+    #[test]
+    fn bias_estimator_rejects_steady_rotation_beyond_bound() {
+        let mut estimator = BiasEstimator::new(0.2, 50, 1.0, GyroCalibration::default());
+
+        // Too far from the zero initial calibration
+        assert_eq!(feed_window(&mut estimator, 50, BIAS, 0.05, REFERENCE), None);
+
+        let start = GyroCalibration {
+            bias: BIAS,
+            temperature_coefficient: [0.0; 3],
+            reference: f32::from(REFERENCE),
+        };
+        let mut estimator = BiasEstimator::new(0.2, 50, 1.0, start);
+
+        // Slowly turning around z at 3 dps
+        let turning = [BIAS[0], BIAS[1], BIAS[2] + 3.0];
+        assert_eq!(
+            feed_window(&mut estimator, 50, turning, 0.05, REFERENCE),
+            None
+        );
+        assert_eq!(estimator.calibration(), start);
+
+        // A small drift is still followed
+        let drifted = [BIAS[0] + 0.5, BIAS[1], BIAS[2] - 0.5];
+        let calibration = feed_window(&mut estimator, 50, drifted, 0.05, REFERENCE).unwrap();
+        for (&actual, &expected) in calibration.bias.iter().zip(drifted.iter()) {
+            assert_close(actual, expected, 1e-4);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Clock presets based on the 8 MHz HSE
+//!
+//! The HSE is fed by the MCO output of the on-board ST-Link, so it is always
+//! available while the board is powered through the ST-Link USB connector.
+
+use crate::hal::prelude::*;
+use crate::hal::rcc::Clocks;
+use crate::hal::stm32;
+use crate::hal::time::Hertz;
+
+/// Frequency of the external clock provided by the ST-Link
+pub const HSE: u32 = 8_000_000;
+
+/// Audio sample rates the PLLI2S can be tuned for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleRate {
+    /// 44.1 kHz
+    Khz44_1,
+    /// 48 kHz
+    Khz48,
+}
+
+/// I2S prescaler matching a PLLI2S configuration
+///
+/// These values go into the `I2SDIV` and `ODD` fields of `SPI_I2SPR`, with
+/// the master clock output enabled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct I2sPrescaler {
+    /// Linear prescaler
+    pub div: u8,
+    /// Odd factor
+    pub odd: bool,
+}
+
+/// Named clock configurations
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Preset {
+    /// 100 MHz system clock, the maximum of the STM32F411
+    Performance,
+    /// 96 MHz system clock with an exact 48 MHz USB clock
+    Usb,
+    /// 96 MHz system clock, exact 48 MHz USB clock and PLLI2S tuned for the
+    /// given sample rate with a 256 × fs master clock
+    Audio(SampleRate),
+    /// 8 MHz system clock straight from the HSE with the PLL off
+    LowPower,
+}
+
+/// Resulting bus and peripheral frequencies of a preset
+#[derive(Copy, Clone, Debug)]
+pub struct ClockTree {
+    /// Preset the tree was built from
+    pub preset: Preset,
+    /// System clock
+    pub sysclk: Hertz,
+    /// AHB clock
+    pub hclk: Hertz,
+    /// APB1 clock (I2C, USART2, SPI2/3)
+    pub pclk1: Hertz,
+    /// APB2 clock (USART1/6, SPI1/4/5)
+    pub pclk2: Hertz,
+    /// Timer clock on APB1 (TIM2 to TIM5)
+    pub timclk1: Hertz,
+    /// Timer clock on APB2 (TIM1, TIM9 to TIM11)
+    pub timclk2: Hertz,
+    /// USB OTG FS clock, if it is exactly 48 MHz
+    pub usb: Option<Hertz>,
+    /// I2S kernel clock from the PLLI2S, if enabled
+    pub i2s: Option<Hertz>,
+    /// I2S prescaler to program for the requested sample rate
+    pub i2s_prescaler: Option<I2sPrescaler>,
+    /// Actual audio sample rate in Hz resulting from the I2S configuration
+    pub sample_rate: Option<f32>,
+}
+
+/// PLLI2S configuration for a sample rate with a 1 MHz VCO input
+struct PllI2s {
+    n: u32,
+    r: u32,
+    prescaler: I2sPrescaler,
+}
+
+impl SampleRate {
+    fn pll_i2s(self) -> PllI2s {
+        match self {
+            SampleRate::Khz44_1 => PllI2s {
+                n: 271,
+                r: 2,
+                prescaler: I2sPrescaler { div: 6, odd: false },
+            },
+            SampleRate::Khz48 => PllI2s {
+                n: 258,
+                r: 3,
+                prescaler: I2sPrescaler { div: 3, odd: true },
+            },
+        }
+    }
+}
+
+impl Preset {
+    /// Configures and freezes the clocks
+    ///
+    /// Returns the frozen clocks together with a description of the
+    /// resulting clock tree.
+    pub fn freeze(self, rcc: stm32::RCC) -> (Clocks, ClockTree) {
+        let cfgr = rcc.constrain().cfgr.use_hse(HSE.hz());
+
+        let clocks = match self {
+            Preset::Performance => cfgr.sysclk(100.mhz()).pclk1(50.mhz()).freeze(),
+            Preset::Usb | Preset::Audio(_) => cfgr
+                .sysclk(96.mhz())
+                .pclk1(48.mhz())
+                .require_pll48clk()
+                .freeze(),
+            Preset::LowPower => cfgr.sysclk(HSE.hz()).freeze(),
+        };
+
+        let mut tree = ClockTree {
+            preset: self,
+            sysclk: clocks.sysclk(),
+            hclk: clocks.hclk(),
+            pclk1: clocks.pclk1(),
+            pclk2: clocks.pclk2(),
+            timclk1: timer_clock(clocks.pclk1(), clocks.ppre1()),
+            timclk2: timer_clock(clocks.pclk2(), clocks.ppre2()),
+            usb: clocks.pll48clk().filter(|clk| clk.0 == 48_000_000),
+            i2s: None,
+            i2s_prescaler: None,
+            sample_rate: None,
+        };
+
+        if let Preset::Audio(rate) = self {
+            let pll = rate.pll_i2s();
+            let i2sclk = enable_pll_i2s(&pll);
+            let divider = 2 * pll.prescaler.div as u32 + pll.prescaler.odd as u32;
+
+            tree.i2s = Some(i2sclk);
+            tree.i2s_prescaler = Some(pll.prescaler);
+            tree.sample_rate = Some(i2sclk.0 as f32 / (256 * divider) as f32);
+        }
+
+        (clocks, tree)
+    }
+}
+
+/// Timers run at twice the APB clock whenever the APB prescaler is not 1
+fn timer_clock(pclk: Hertz, ppre: u8) -> Hertz {
+    if ppre == 1 {
+        pclk
+    } else {
+        Hertz(pclk.0 * 2)
+    }
+}
+
+/// Starts the PLLI2S from the HSE and returns the I2S kernel clock
+fn enable_pll_i2s(pll: &PllI2s) -> Hertz {
+    // PLLI2SM divides the HSE down to a 1 MHz VCO input
+    let m = HSE / 1_000_000;
+
+    // The RCC was consumed by the HAL, which does not touch the PLLI2S
+    let rcc = unsafe { &*stm32::RCC::ptr() };
+
+    rcc.cr.modify(|_, w| w.plli2son().clear_bit());
+    rcc.plli2scfgr
+        .write(|w| unsafe { w.bits((pll.r << 28) | (pll.n << 6) | m) });
+    rcc.cr.modify(|_, w| w.plli2son().set_bit());
+    while rcc.cr.read().plli2srdy().bit_is_clear() {}
+
+    Hertz(1_000_000 * pll.n / pll.r)
+}
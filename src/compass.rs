@@ -1,88 +1,400 @@
-use accelerometer;
-use lsm303dlhc;
+//! LSM303DLHC / LSM303AGR magnetometer on I2C1
 
-use crate::hal::gpio;
-use crate::hal::gpio::gpiob;
-use crate::hal::gpio::gpioe;
-use crate::hal::prelude::*;
-use crate::hal::rcc;
+use core::fmt::Debug;
+
+use accelerometer::vector::{F32x3, I16x3};
+
+use crate::bus::{self, I2c1};
+use crate::calibration::MagCalibration;
+use crate::hal::gpio::{self, gpiob};
 use crate::hal::i2c;
+use crate::hal::rcc;
 use crate::hal::stm32;
+use crate::revision::{self, EcompassVariant};
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// I2C address of the magnetometer die
+const ADDRESS: u8 = 0x1E;
+
+/// Sub-address bit enabling register auto-increment on the LSM303AGR
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// LSM303AGR sensitivity in gauss/LSB
+const LSM303AGR_SENSITIVITY: f32 = 0.0015;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+enum Register {
+    // LSM303DLHC
+    CRA_REG_M = 0x00,
+    CRB_REG_M = 0x01,
+    MR_REG_M = 0x02,
+    OUT_X_H_M = 0x03,
+    SR_REG_M = 0x09,
+    // LSM303AGR
+    OFFSET_X_REG_L_M = 0x45,
+    CFG_REG_A_M = 0x60,
+    CFG_REG_B_M = 0x61,
+    CFG_REG_C_M = 0x62,
+    STATUS_REG_M = 0x67,
+    OUTX_L_REG_M = 0x68,
+}
+
+/// Magnetometer full scale range
+///
+/// Only applies to the LSM303DLHC, the LSM303AGR has a fixed ±50 gauss range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gain {
+    /// ±1.3 gauss
+    Gauss1_3 = 1,
+    /// ±1.9 gauss
+    Gauss1_9 = 2,
+    /// ±2.5 gauss
+    Gauss2_5 = 3,
+    /// ±4.0 gauss
+    Gauss4_0 = 4,
+    /// ±4.7 gauss
+    Gauss4_7 = 5,
+    /// ±5.6 gauss
+    Gauss5_6 = 6,
+    /// ±8.1 gauss
+    Gauss8_1 = 7,
+}
 
-use embedded_hal;
-use embedded_hal::digital::v2::OutputPin;
+impl Gain {
+    /// Sensitivity of the X/Y and Z axes in LSB/gauss
+    fn sensitivity(self) -> (f32, f32) {
+        match self {
+            Gain::Gauss1_3 => (1100.0, 980.0),
+            Gain::Gauss1_9 => (855.0, 760.0),
+            Gain::Gauss2_5 => (670.0, 600.0),
+            Gain::Gauss4_0 => (450.0, 400.0),
+            Gain::Gauss4_7 => (400.0, 355.0),
+            Gain::Gauss5_6 => (330.0, 295.0),
+            Gain::Gauss8_1 => (230.0, 205.0),
+        }
+    }
+}
+
+/// Magnetometer output data rate
+///
+/// The LSM303AGR only supports 10, 20, 50 and 100 Hz, so the closest of those
+/// is used on that part.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DataRate {
+    /// 0.75 Hz
+    Hz0_75 = 0,
+    /// 1.5 Hz
+    Hz1_5 = 1,
+    /// 3 Hz
+    Hz3 = 2,
+    /// 7.5 Hz
+    Hz7_5 = 3,
+    /// 15 Hz
+    Hz15 = 4,
+    /// 30 Hz
+    Hz30 = 5,
+    /// 75 Hz
+    Hz75 = 6,
+    /// 220 Hz
+    Hz220 = 7,
+}
+
+impl DataRate {
+    /// Closest LSM303AGR rate, as the ODR field value and in Hz
+    fn lsm303agr(self) -> (u8, f32) {
+        match self {
+            DataRate::Hz0_75 | DataRate::Hz1_5 | DataRate::Hz3 | DataRate::Hz7_5 => (0b00, 10.0),
+            DataRate::Hz15 => (0b01, 20.0),
+            DataRate::Hz30 => (0b10, 50.0),
+            DataRate::Hz75 | DataRate::Hz220 => (0b11, 100.0),
+        }
+    }
 
-type I2c1=i2c::I2c<
-    stm32::I2C1,
-    (
-        gpiob::PB6<gpio::Alternate<gpio::AF4>>,
-        gpiob::PB7<gpio::Alternate<gpio::AF4>>,
-    ),
->;
+    /// Output data rate in Hz
+    pub fn hz(self) -> f32 {
+        match self {
+            DataRate::Hz0_75 => 0.75,
+            DataRate::Hz1_5 => 1.5,
+            DataRate::Hz3 => 3.0,
+            DataRate::Hz7_5 => 7.5,
+            DataRate::Hz15 => 15.0,
+            DataRate::Hz30 => 30.0,
+            DataRate::Hz75 => 75.0,
+            DataRate::Hz220 => 220.0,
+        }
+    }
+}
 
-pub struct Compass {
-    lsm303dlhc: lsm303dlhc::lsm303dlhc<I2c1>
+/// On-board magnetometer
+pub struct Compass<I2C = I2c1> {
+    i2c: I2C,
+    variant: EcompassVariant,
+    gain: Gain,
+    data_rate: DataRate,
+    calibration: Option<MagCalibration>,
 }
 
-impl Compass {
+impl Compass<I2c1> {
+    /// Configures I2C1 on PB6/PB9, detects the e-compass variant and
+    /// initializes the magnetometer
     pub fn new(
-        gpiob: gpiob::Parts,
-        gpioe: gpioe::Parts,
+        scl: gpiob::PB6<gpio::Input<gpio::Floating>>,
+        sda: gpiob::PB9<gpio::Input<gpio::Floating>>,
         i2c1: stm32::I2C1,
         clocks: rcc::Clocks,
-    )-> Self{
-        let scl = gpiob.pb6.into_alternate_af4().internal_pull_up(true).set_open_drain();
-        let sda = gpiob.pb7.into_alternate_af4().internal_pull_up(true).set_open_drain();
+    ) -> Result<Self, revision::Error<i2c::Error>> {
+        let mut i2c = bus::i2c1(scl, sda, i2c1, clocks);
+        let variant = revision::probe_ecompass(&mut i2c)?;
+
+        Ok(Self::from_i2c(i2c, variant)?)
+    }
+}
 
-        let i2c_mode = i2c::Mode {
-            polarity: i2c::Polarity::IdleLow,
-            phase: i2c::Phase::CaptureOnFirstTransition,
+impl<I2C, E> Compass<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+{
+    /// Initializes the magnetometer on an already configured I2C bus
+    ///
+    /// The sensor is put in continuous conversion mode at 15 Hz (20 Hz on the
+    /// LSM303AGR) and ±1.3 gauss.
+    pub fn from_i2c(i2c: I2C, variant: EcompassVariant) -> Result<Self, E> {
+        let mut compass = Self {
+            i2c,
+            variant,
+            gain: Gain::Gauss1_3,
+            data_rate: DataRate::Hz15,
+            calibration: None,
         };
 
-        let i2c = i2c::I2c::i2c1(i2c1, (scl,sda), i2c_mode, 400.khz().into(), clocks);
+        match variant {
+            EcompassVariant::Lsm303dlhc => {
+                compass.set_data_rate(DataRate::Hz15)?;
+                compass.set_gain(Gain::Gauss1_3)?;
+                // Continuous conversion mode
+                compass.write_register(Register::MR_REG_M, 0x00)?;
+            }
+            EcompassVariant::Lsm303agr => {
+                // Block data update
+                compass.write_register(Register::CFG_REG_C_M, 0x10)?;
+                // Temperature compensation, continuous conversion mode
+                compass.write_register(Register::CFG_REG_A_M, 0x80)?;
+                compass.set_data_rate(DataRate::Hz15)?;
+            }
+        }
 
-        let config = lsm303dlhc::Config {
-            scale: lsm303dlhc::Scale::PlusMinus8G,
-            ..Default::default()
-        };
-        let lsm303dlhc = lsm303dlhc::lsm303dlhc::new(i2c,config);
+        Ok(compass)
+    }
 
-        Self{ lsm303dlhc }
+    /// Releases the underlying I2C bus
+    pub fn release(self) -> I2C {
+        self.i2c
     }
-}
 
-impl compass::RawAccelerometer<accelerometer::vector::I8x3> for lsm303dlhc {
-    type Error = i2c::Error;
-    fn accel_raw(
-        &mut self,
-    ) -> Result<accelerometer::vector::I8x3, accelerometer::Error<Self::Error>> {
-        let x = self.read_accel_registers(accel::Registers::OUT_X_L_A);
-        let y = self.read_accel_registers(accel::Registers::OUT_Y_L_A);
-        let z = self.read_accel_registers(accel::Registers::OUT_Z_L_A);
-        Ok(accelerometer::vector::I8x3::new(
-            i8::from_le_bytes([x]),
-            i8::from_le_bytes([y]),
-            i8::from_le_bytes([z]),
-        ))
+    /// Returns the e-compass part this driver talks to
+    pub fn variant(&self) -> EcompassVariant {
+        self.variant
     }
-}
 
-impl compass::Accelerometer for lsm303dlhc {
-    type Error = spi::Error;
-    fn sample_rate(&mut self) -> Result<f32, accelerometer::Error<Self::Error>> {
-        self.modify_accel_register(accel::Register::CTRL_REG1_A, |r| {
-            r & !(0b1111 << 4) | ((odr as u8) << 4)
+    /// Sets the full scale range
+    pub fn set_gain(&mut self, gain: Gain) -> Result<(), E> {
+        if self.variant == EcompassVariant::Lsm303dlhc {
+            self.write_register(Register::CRB_REG_M, (gain as u8) << 5)?;
+        }
+        self.gain = gain;
+        Ok(())
+    }
+
+    /// Returns the configured full scale range
+    pub fn gain(&self) -> Gain {
+        self.gain
+    }
+
+    /// Sets the output data rate
+    pub fn set_data_rate(&mut self, data_rate: DataRate) -> Result<(), E> {
+        match self.variant {
+            EcompassVariant::Lsm303dlhc => {
+                self.write_register(Register::CRA_REG_M, (data_rate as u8) << 2)?;
+            }
+            EcompassVariant::Lsm303agr => {
+                let (odr, _) = data_rate.lsm303agr();
+                self.modify_register(Register::CFG_REG_A_M, |r| (r & !0b0000_1100) | (odr << 2))?;
+            }
+        }
+        self.data_rate = data_rate;
+        Ok(())
+    }
+
+    /// Returns the configured output data rate
+    pub fn data_rate(&self) -> DataRate {
+        self.data_rate
+    }
+
+    /// Enables the LSM303AGR offset cancellation
+    ///
+    /// The part then periodically flips its set/reset pulse to remove the
+    /// sensor offset from every sample. Ignored on the LSM303DLHC.
+    pub fn set_offset_cancellation(&mut self, enabled: bool) -> Result<(), E> {
+        if self.variant != EcompassVariant::Lsm303agr {
+            return Ok(());
+        }
+
+        self.modify_register(Register::CFG_REG_B_M, |r| {
+            (r & !0b0000_0010) | ((enabled as u8) << 1)
         })
     }
 
-    fn accel_norm(
-        &mut self,
-    ) -> Result<accelerometer::vector::F32x3, accelerometer::Error<Self::Error>> {
-        let raw_acceleration: accelerometer::vector::I8x3 = self.accel_raw().unwrap();
-        Ok(accelerometer::vector::F32x3::new(
-            raw_acceleration.x as f32 * SCALE,
-            raw_acceleration.y as f32 * SCALE,
-            raw_acceleration.z as f32 * SCALE,
-        ))
+    /// Programs the LSM303AGR hard-iron offset, in raw LSB
+    ///
+    /// The offset is subtracted by the sensor from every sample. Ignored on
+    /// the LSM303DLHC.
+    pub fn set_hard_iron_offset(&mut self, offset: I16x3) -> Result<(), E> {
+        if self.variant != EcompassVariant::Lsm303agr {
+            return Ok(());
+        }
+
+        let [xl, xh] = offset.x.to_le_bytes();
+        let [yl, yh] = offset.y.to_le_bytes();
+        let [zl, zh] = offset.z.to_le_bytes();
+        self.i2c.write(
+            ADDRESS,
+            &[
+                Register::OFFSET_X_REG_L_M as u8 | AUTO_INCREMENT,
+                xl,
+                xh,
+                yl,
+                yh,
+                zl,
+                zh,
+            ],
+        )
+    }
+
+    /// Drives the data ready signal on the magnetometer interrupt pin (PE2)
+    ///
+    /// The LSM303DLHC always drives its DRDY pin, so this only has an effect
+    /// on the LSM303AGR.
+    pub fn enable_data_ready_interrupt(&mut self, enabled: bool) -> Result<(), E> {
+        if self.variant != EcompassVariant::Lsm303agr {
+            return Ok(());
+        }
+
+        self.modify_register(Register::CFG_REG_C_M, |r| {
+            (r & !0b0000_0001) | enabled as u8
+        })
+    }
+
+    /// Returns the actual output data rate in Hz
+    pub fn sample_rate(&self) -> f32 {
+        match self.variant {
+            EcompassVariant::Lsm303dlhc => self.data_rate.hz(),
+            EcompassVariant::Lsm303agr => self.data_rate.lsm303agr().1,
+        }
+    }
+
+    /// Returns `true` if a new sample is available
+    pub fn data_ready(&mut self) -> Result<bool, E> {
+        Ok(match self.variant {
+            EcompassVariant::Lsm303dlhc => self.read_register(Register::SR_REG_M)? & 0x01 != 0,
+            EcompassVariant::Lsm303agr => self.read_register(Register::STATUS_REG_M)? & 0x08 != 0,
+        })
     }
-}
\ No newline at end of file
+
+    /// Reads the raw magnetic field sample
+    pub fn mag_raw(&mut self) -> Result<I16x3, E> {
+        let mut buffer = [0u8; 6];
+
+        match self.variant {
+            EcompassVariant::Lsm303dlhc => {
+                self.i2c
+                    .write_read(ADDRESS, &[Register::OUT_X_H_M as u8], &mut buffer)?;
+
+                // The output registers are big endian and ordered X, Z, Y
+                Ok(I16x3::new(
+                    i16::from_be_bytes([buffer[0], buffer[1]]),
+                    i16::from_be_bytes([buffer[4], buffer[5]]),
+                    i16::from_be_bytes([buffer[2], buffer[3]]),
+                ))
+            }
+            EcompassVariant::Lsm303agr => {
+                self.i2c.write_read(
+                    ADDRESS,
+                    &[Register::OUTX_L_REG_M as u8 | AUTO_INCREMENT],
+                    &mut buffer,
+                )?;
+
+                Ok(I16x3::new(
+                    i16::from_le_bytes([buffer[0], buffer[1]]),
+                    i16::from_le_bytes([buffer[2], buffer[3]]),
+                    i16::from_le_bytes([buffer[4], buffer[5]]),
+                ))
+            }
+        }
+    }
+
+    /// Sets the hard-iron and soft-iron correction applied to every reading
+    /// in gauss
+    ///
+    /// See [`EllipsoidFit`](crate::calibration::EllipsoidFit).
+    pub fn set_calibration(&mut self, calibration: Option<MagCalibration>) {
+        self.calibration = calibration;
+    }
+
+    /// Returns the calibration in use
+    pub fn calibration(&self) -> Option<MagCalibration> {
+        self.calibration
+    }
+
+    /// Reads the magnetic field in gauss, scaled according to the configured
+    /// gain and with the calibration applied
+    pub fn mag_gauss(&mut self) -> Result<F32x3, E> {
+        let raw = self.mag_raw()?;
+        let (xy, z) = match self.variant {
+            EcompassVariant::Lsm303dlhc => {
+                let (xy, z) = self.gain.sensitivity();
+                (1.0 / xy, 1.0 / z)
+            }
+            EcompassVariant::Lsm303agr => (LSM303AGR_SENSITIVITY, LSM303AGR_SENSITIVITY),
+        };
+
+        let field = F32x3::new(raw.x as f32 * xy, raw.y as f32 * xy, raw.z as f32 * z);
+        Ok(match self.calibration {
+            Some(calibration) => calibration.apply(field),
+            None => field,
+        })
+    }
+
+    /// Returns the heading in degrees (0..360) assuming the board lies flat
+    pub fn heading(&mut self) -> Result<f32, E> {
+        let field = self.mag_gauss()?;
+        let heading = libm::atan2f(field.y, field.x).to_degrees();
+
+        Ok(if heading < 0.0 {
+            heading + 360.0
+        } else {
+            heading
+        })
+    }
+
+    fn write_register(&mut self, register: Register, value: u8) -> Result<(), E> {
+        self.i2c.write(ADDRESS, &[register as u8, value])
+    }
+
+    fn read_register(&mut self, register: Register) -> Result<u8, E> {
+        let mut buffer = [0u8];
+        self.i2c
+            .write_read(ADDRESS, &[register as u8], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    fn modify_register<F>(&mut self, register: Register, f: F) -> Result<(), E>
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        let value = self.read_register(register)?;
+        self.write_register(register, f(value))
+    }
+}
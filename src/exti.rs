@@ -0,0 +1,48 @@
+//! EXTI lines for the MEMS sensor interrupt outputs
+//!
+//! The sensors drive their interrupt outputs push-pull, so the lines are
+//! used as floating inputs. The matching `EXTIx` interrupt still has to be
+//! unmasked in the NVIC: EXTI0 (PE0), EXTI1 (PE1), EXTI2 (PE2), EXTI4 (PE4)
+//! and EXTI9_5 (PE5).
+//!
+//! Each EXTI line serves a single port, so PE0 and the user button on PA0
+//! cannot both use EXTI0.
+
+use crate::hal::gpio::{Edge, ExtiPin};
+use crate::hal::stm32::EXTI;
+use crate::hal::syscfg::SysCfg;
+
+/// A sensor interrupt output routed to its EXTI line
+pub struct InterruptLine<PIN> {
+    pin: PIN,
+}
+
+impl<PIN> InterruptLine<PIN>
+where
+    PIN: ExtiPin,
+{
+    /// Connects the pin to its EXTI line and enables the interrupt on `edge`
+    pub fn new(mut pin: PIN, syscfg: &mut SysCfg, exti: &mut EXTI, edge: Edge) -> Self {
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, edge);
+        pin.enable_interrupt(exti);
+
+        InterruptLine { pin }
+    }
+
+    /// Returns `true` if the EXTI line has a pending interrupt
+    pub fn is_pending(&self) -> bool {
+        self.pin.check_interrupt()
+    }
+
+    /// Clears the pending bit, call this from the `EXTIx` handler
+    pub fn clear(&mut self) {
+        self.pin.clear_interrupt_pending_bit();
+    }
+
+    /// Disables the EXTI line and returns the pin
+    pub fn release(mut self, exti: &mut EXTI) -> PIN {
+        self.pin.disable_interrupt(exti);
+        self.pin
+    }
+}
@@ -0,0 +1,971 @@
+//! Attitude and heading reference system
+//!
+//! Fuses the gyroscope with the accelerometer (6-DoF) and optionally the
+//! magnetometer (9-DoF) into the orientation of the board. All three
+//! sensors share the board axes: x towards the red LED, y towards the
+//! orange LED and z out of the components side.
+//!
+//! The orientation is given relative to an earth frame with x pointing to
+//! magnetic north, y to the west and z up. Without the magnetometer, north
+//! is wherever the board x axis pointed at the first update, and the yaw
+//! drifts with the gyroscope bias.
+
+use core::fmt::Debug;
+use core::ops::Mul;
+
+use accelerometer::vector::F32x3;
+
+use crate::accelerometer::Accelerometer;
+use crate::compass::Compass;
+use crate::gyroscope::{self, Gyroscope};
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use embedded_hal::blocking::spi;
+use embedded_hal::digital::v2::OutputPin;
+
+/// Rotation quaternion
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    /// Scalar part
+    pub w: f32,
+    /// X component of the vector part
+    pub x: f32,
+    /// Y component of the vector part
+    pub y: f32,
+    /// Z component of the vector part
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+impl Quaternion {
+    /// No rotation
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Returns the inverse rotation of a unit quaternion
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Scales the quaternion to unit length, identity if it is zero
+    pub fn normalize(self) -> Quaternion {
+        let norm =
+            libm::sqrtf(self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z);
+        if norm == 0.0 {
+            return Self::IDENTITY;
+        }
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Rotates a vector from the board frame into the earth frame
+    pub fn rotate(&self, v: F32x3) -> F32x3 {
+        let [x, y, z] = mul(&self.rotation_matrix(), [v.x, v.y, v.z]);
+        F32x3::new(x, y, z)
+    }
+
+    /// Rotation matrix from the board frame into the earth frame, row-major
+    pub fn rotation_matrix(&self) -> [[f32; 3]; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Quaternion of a rotation matrix, which must be orthonormal
+    pub fn from_rotation_matrix(r: &[[f32; 3]; 3]) -> Quaternion {
+        // Shepperd's method, dividing by the largest of the four components
+        let trace = r[0][0] + r[1][1] + r[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * libm::sqrtf(1.0 + trace);
+            Quaternion {
+                w: s / 4.0,
+                x: (r[2][1] - r[1][2]) / s,
+                y: (r[0][2] - r[2][0]) / s,
+                z: (r[1][0] - r[0][1]) / s,
+            }
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = 2.0 * libm::sqrtf(1.0 + r[0][0] - r[1][1] - r[2][2]);
+            Quaternion {
+                w: (r[2][1] - r[1][2]) / s,
+                x: s / 4.0,
+                y: (r[0][1] + r[1][0]) / s,
+                z: (r[0][2] + r[2][0]) / s,
+            }
+        } else if r[1][1] > r[2][2] {
+            let s = 2.0 * libm::sqrtf(1.0 + r[1][1] - r[0][0] - r[2][2]);
+            Quaternion {
+                w: (r[0][2] - r[2][0]) / s,
+                x: (r[0][1] + r[1][0]) / s,
+                y: s / 4.0,
+                z: (r[1][2] + r[2][1]) / s,
+            }
+        } else {
+            let s = 2.0 * libm::sqrtf(1.0 + r[2][2] - r[0][0] - r[1][1]);
+            Quaternion {
+                w: (r[1][0] - r[0][1]) / s,
+                x: (r[0][2] + r[2][0]) / s,
+                y: (r[1][2] + r[2][1]) / s,
+                z: s / 4.0,
+            }
+        };
+        q.normalize()
+    }
+
+    /// Tait-Bryan angles of the rotation, applied in yaw, pitch, roll order
+    pub fn to_euler(&self) -> EulerAngles {
+        let Quaternion { w, x, y, z } = *self;
+        EulerAngles {
+            roll: libm::atan2f(2.0 * (w * x + y * z), 1.0 - 2.0 * (x * x + y * y)).to_degrees(),
+            pitch: libm::asinf((2.0 * (w * y - x * z)).clamp(-1.0, 1.0)).to_degrees(),
+            yaw: libm::atan2f(2.0 * (w * z + x * y), 1.0 - 2.0 * (y * y + z * z)).to_degrees(),
+        }
+    }
+}
+
+/// Orientation as Euler angles, all in degrees
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EulerAngles {
+    /// Rotation about the board x axis, from -180 to 180
+    pub roll: f32,
+    /// Rotation about the board y axis, from -90 to 90
+    pub pitch: f32,
+    /// Rotation about the vertical, counterclockwise from north to the
+    /// board x axis seen from above, from -180 to 180
+    pub yaw: f32,
+}
+
+/// Orientation filter algorithm
+pub trait Filter {
+    /// Integrates one sample period `dt`, in s, into `q`
+    ///
+    /// `gyro` is in rad/s. `accel` and `field`, if given, are unit vectors
+    /// in the board frame.
+    fn update(
+        &mut self,
+        q: &mut Quaternion,
+        gyro: [f32; 3],
+        accel: [f32; 3],
+        field: Option<[f32; 3]>,
+        dt: f32,
+    );
+
+    /// Clears any internal state
+    fn reset(&mut self) {}
+}
+
+/// Madgwick gradient descent filter
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Madgwick {
+    /// Gain of the accelerometer and magnetometer correction, in rad/s
+    pub beta: f32,
+}
+
+impl Default for Madgwick {
+    fn default() -> Self {
+        Madgwick { beta: 0.1 }
+    }
+}
+
+impl Madgwick {
+    /// Creates a filter with the given gain
+    pub fn new(beta: f32) -> Self {
+        Madgwick { beta }
+    }
+}
+
+impl Filter for Madgwick {
+    fn update(
+        &mut self,
+        q: &mut Quaternion,
+        gyro: [f32; 3],
+        accel: [f32; 3],
+        field: Option<[f32; 3]>,
+        dt: f32,
+    ) {
+        let Quaternion {
+            w: q0,
+            x: q1,
+            y: q2,
+            z: q3,
+        } = *q;
+        let [ax, ay, az] = accel;
+
+        // Objective function towards gravity along the earth z axis and its
+        // Jacobian
+        let mut f = [0.0; 6];
+        let mut j = [[0.0; 4]; 6];
+        f[0] = 2.0 * (q1 * q3 - q0 * q2) - ax;
+        f[1] = 2.0 * (q0 * q1 + q2 * q3) - ay;
+        f[2] = 1.0 - 2.0 * (q1 * q1 + q2 * q2) - az;
+        j[0] = [-2.0 * q2, 2.0 * q3, -2.0 * q0, 2.0 * q1];
+        j[1] = [2.0 * q1, 2.0 * q0, 2.0 * q3, 2.0 * q2];
+        j[2] = [0.0, -4.0 * q1, -4.0 * q2, 0.0];
+
+        // Same towards the field, in the earth x-z plane
+        let rows = if let Some([mx, my, mz]) = field {
+            let h = q.rotate(F32x3::new(mx, my, mz));
+            let bx = libm::sqrtf(h.x * h.x + h.y * h.y);
+            let bz = h.z;
+
+            f[3] = 2.0 * bx * (0.5 - q2 * q2 - q3 * q3) + 2.0 * bz * (q1 * q3 - q0 * q2) - mx;
+            f[4] = 2.0 * bx * (q1 * q2 - q0 * q3) + 2.0 * bz * (q0 * q1 + q2 * q3) - my;
+            f[5] = 2.0 * bx * (q0 * q2 + q1 * q3) + 2.0 * bz * (0.5 - q1 * q1 - q2 * q2) - mz;
+            j[3] = [
+                -2.0 * bz * q2,
+                2.0 * bz * q3,
+                -4.0 * bx * q2 - 2.0 * bz * q0,
+                -4.0 * bx * q3 + 2.0 * bz * q1,
+            ];
+            j[4] = [
+                -2.0 * bx * q3 + 2.0 * bz * q1,
+                2.0 * bx * q2 + 2.0 * bz * q0,
+                2.0 * bx * q1 + 2.0 * bz * q3,
+                -2.0 * bx * q0 + 2.0 * bz * q2,
+            ];
+            j[5] = [
+                2.0 * bx * q2,
+                2.0 * bx * q3 - 4.0 * bz * q1,
+                2.0 * bx * q0 - 4.0 * bz * q2,
+                2.0 * bx * q1,
+            ];
+            6
+        } else {
+            3
+        };
+
+        // Gradient Jᵀ f, applied as a step of length beta
+        let mut step = [0.0; 4];
+        for (row, value) in j.iter().zip(f.iter()).take(rows) {
+            for (s, d) in step.iter_mut().zip(row.iter()) {
+                *s += d * value;
+            }
+        }
+        let norm = libm::sqrtf(step.iter().map(|s| s * s).sum());
+        if norm > 0.0 {
+            for s in step.iter_mut() {
+                *s *= self.beta / norm;
+            }
+        }
+
+        let rate = rate_of_change(q, gyro);
+        *q = Quaternion {
+            w: q0 + (rate.w - step[0]) * dt,
+            x: q1 + (rate.x - step[1]) * dt,
+            y: q2 + (rate.y - step[2]) * dt,
+            z: q3 + (rate.z - step[3]) * dt,
+        }
+        .normalize();
+    }
+}
+
+/// Mahony complementary filter with proportional and integral feedback
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mahony {
+    /// Proportional gain of the correction
+    pub kp: f32,
+    /// Integral gain of the correction, which also estimates the gyroscope
+    /// bias
+    pub ki: f32,
+    integral: [f32; 3],
+}
+
+impl Default for Mahony {
+    fn default() -> Self {
+        Mahony::new(0.5, 0.0)
+    }
+}
+
+impl Mahony {
+    /// Creates a filter with the given gains
+    pub fn new(kp: f32, ki: f32) -> Self {
+        Mahony {
+            kp,
+            ki,
+            integral: [0.0; 3],
+        }
+    }
+
+    /// Returns the gyroscope bias estimated by the integral feedback, in
+    /// rad/s, with the opposite sign
+    pub fn integral(&self) -> [f32; 3] {
+        self.integral
+    }
+}
+
+impl Filter for Mahony {
+    fn update(
+        &mut self,
+        q: &mut Quaternion,
+        gyro: [f32; 3],
+        accel: [f32; 3],
+        field: Option<[f32; 3]>,
+        dt: f32,
+    ) {
+        let r = q.rotation_matrix();
+
+        // Error between the measured and estimated directions of gravity
+        let mut error = cross(accel, r[2]);
+
+        // And of the field, with the reference in the earth x-z plane
+        if let Some(m) = field {
+            let h = mul(&r, m);
+            let b = [libm::sqrtf(h[0] * h[0] + h[1] * h[1]), 0.0, h[2]];
+            let estimate = mul(&transpose(&r), b);
+            let e = cross(m, estimate);
+            for (error, e) in error.iter_mut().zip(e.iter()) {
+                *error += e;
+            }
+        }
+
+        let mut gyro = gyro;
+        for ((g, e), integral) in gyro
+            .iter_mut()
+            .zip(error.iter())
+            .zip(self.integral.iter_mut())
+        {
+            if self.ki > 0.0 {
+                *integral += self.ki * e * dt;
+                *g += *integral;
+            }
+            *g += self.kp * e;
+        }
+
+        let rate = rate_of_change(q, gyro);
+        *q = Quaternion {
+            w: q.w + rate.w * dt,
+            x: q.x + rate.x * dt,
+            y: q.y + rate.y * dt,
+            z: q.z + rate.z * dt,
+        }
+        .normalize();
+    }
+
+    fn reset(&mut self) {
+        self.integral = [0.0; 3];
+    }
+}
+
+/// Errors while reading the sensors
+#[derive(Debug)]
+pub enum Error<SE, IE> {
+    /// Gyroscope error
+    Gyroscope(gyroscope::Error<SE>),
+    /// I2C bus error of the accelerometer or magnetometer
+    I2c(IE),
+}
+
+/// Orientation estimator running one of the filters at a fixed rate
+pub struct Ahrs<F> {
+    filter: F,
+    sample_period: f32,
+    quaternion: Option<Quaternion>,
+    accel: [f32; 3],
+}
+
+impl<F> Ahrs<F>
+where
+    F: Filter,
+{
+    /// Creates an estimator updated `sample_rate` times per second
+    pub fn new(filter: F, sample_rate: f32) -> Self {
+        Ahrs {
+            filter,
+            sample_period: 1.0 / sample_rate,
+            quaternion: None,
+            accel: [0.0; 3],
+        }
+    }
+
+    /// Changes the update rate, in Hz
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_period = 1.0 / sample_rate;
+    }
+
+    /// Returns the update rate, in Hz
+    pub fn sample_rate(&self) -> f32 {
+        1.0 / self.sample_period
+    }
+
+    /// Returns the filter, e.g. to change its gains
+    pub fn filter(&mut self) -> &mut F {
+        &mut self.filter
+    }
+
+    /// Forgets the orientation, the next update starts over from the
+    /// accelerometer and magnetometer alone
+    pub fn reset(&mut self) {
+        self.quaternion = None;
+        self.filter.reset();
+    }
+
+    /// Feeds one sample: the angular rate in dps, the acceleration in g and
+    /// optionally the field, in any unit
+    ///
+    /// The first update sets the orientation directly from gravity and the
+    /// field. Updates with a zero acceleration are ignored, a zero field
+    /// falls back to 6-DoF.
+    pub fn update(&mut self, gyro: F32x3, accel: F32x3, field: Option<F32x3>) {
+        let a = [accel.x, accel.y, accel.z];
+        let unit_accel = match normalize(a) {
+            Some(unit) => unit,
+            None => return,
+        };
+        self.accel = a;
+        let unit_field = field.and_then(|m| normalize([m.x, m.y, m.z]));
+
+        let mut q = match self.quaternion {
+            Some(q) => q,
+            None => {
+                self.quaternion = Some(initial(unit_accel, unit_field));
+                return;
+            }
+        };
+
+        let gyro = [
+            gyro.x.to_radians(),
+            gyro.y.to_radians(),
+            gyro.z.to_radians(),
+        ];
+        self.filter
+            .update(&mut q, gyro, unit_accel, unit_field, self.sample_period);
+        self.quaternion = Some(q);
+    }
+
+    /// Reads the gyroscope and accelerometer and runs a 6-DoF update
+    pub fn poll<SPI, CS, SE, I2C, IE>(
+        &mut self,
+        gyroscope: &mut Gyroscope<SPI, CS>,
+        accelerometer: &mut Accelerometer<I2C>,
+    ) -> Result<(), Error<SE, IE>>
+    where
+        SPI: spi::Transfer<u8, Error = SE> + spi::Write<u8, Error = SE>,
+        CS: OutputPin,
+        SE: Debug,
+        I2C: WriteRead<Error = IE> + Write<Error = IE>,
+        IE: Debug,
+    {
+        let gyro = gyroscope.gyro_dps().map_err(Error::Gyroscope)?;
+        let raw = accelerometer.read_sample().map_err(Error::I2c)?;
+        self.update(gyro, accelerometer.to_g(raw), None);
+        Ok(())
+    }
+
+    /// Reads all three sensors and runs a 9-DoF update
+    pub fn poll_with_compass<SPI, CS, SE, I2C, IE>(
+        &mut self,
+        gyroscope: &mut Gyroscope<SPI, CS>,
+        accelerometer: &mut Accelerometer<I2C>,
+        compass: &mut Compass<I2C>,
+    ) -> Result<(), Error<SE, IE>>
+    where
+        SPI: spi::Transfer<u8, Error = SE> + spi::Write<u8, Error = SE>,
+        CS: OutputPin,
+        SE: Debug,
+        I2C: WriteRead<Error = IE> + Write<Error = IE>,
+        IE: Debug,
+    {
+        let gyro = gyroscope.gyro_dps().map_err(Error::Gyroscope)?;
+        let raw = accelerometer.read_sample().map_err(Error::I2c)?;
+        let field = compass.mag_gauss().map_err(Error::I2c)?;
+        self.update(gyro, accelerometer.to_g(raw), Some(field));
+        Ok(())
+    }
+
+    /// Returns the orientation, identity before the first update
+    pub fn quaternion(&self) -> Quaternion {
+        self.quaternion.unwrap_or_default()
+    }
+
+    /// Returns the orientation as Euler angles
+    pub fn euler(&self) -> EulerAngles {
+        self.quaternion().to_euler()
+    }
+
+    /// Returns the heading of the top (orange LED) edge in degrees, clockwise
+    /// from north, from 0 to 360
+    pub fn heading(&self) -> f32 {
+        let r = self.quaternion().rotation_matrix();
+        // The board y axis in the earth frame, y pointing west
+        let heading = libm::atan2f(-r[1][1], r[0][1]).to_degrees();
+        if heading < 0.0 {
+            heading + 360.0
+        } else {
+            heading
+        }
+    }
+
+    /// Returns the last acceleration with gravity removed, in g, in the
+    /// board frame
+    pub fn linear_acceleration(&self) -> F32x3 {
+        let gravity = self.quaternion().rotation_matrix()[2];
+        F32x3::new(
+            self.accel[0] - gravity[0],
+            self.accel[1] - gravity[1],
+            self.accel[2] - gravity[2],
+        )
+    }
+}
+
+/// Orientation from gravity and the field alone
+///
+/// Without the field, the board x axis projected on the horizontal plane is
+/// taken as north.
+fn initial(up: [f32; 3], field: Option<[f32; 3]>) -> Quaternion {
+    // The earth axes in the board frame are the rows of the rotation matrix
+    let east = field.and_then(|m| normalize(cross(m, up)));
+    let north = match east {
+        Some(east) => cross(up, east),
+        None => {
+            let reference = if up[0].abs() < 0.9 {
+                [1.0, 0.0, 0.0]
+            } else {
+                [0.0, 1.0, 0.0]
+            };
+            let along = dot(reference, up);
+            let horizontal = [
+                reference[0] - along * up[0],
+                reference[1] - along * up[1],
+                reference[2] - along * up[2],
+            ];
+            match normalize(horizontal) {
+                Some(north) => north,
+                None => return Quaternion::IDENTITY,
+            }
+        }
+    };
+    let west = cross(up, north);
+
+    Quaternion::from_rotation_matrix(&[north, west, up])
+}
+
+/// Derivative of `q` rotating at `gyro` rad/s in the board frame
+fn rate_of_change(q: &Quaternion, gyro: [f32; 3]) -> Quaternion {
+    let [x, y, z] = gyro;
+    *q * Quaternion {
+        w: 0.0,
+        x: x / 2.0,
+        y: y / 2.0,
+        z: z / 2.0,
+    }
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = libm::sqrtf(dot(v, v));
+    if norm == 0.0 {
+        None
+    } else {
+        Some([v[0] / norm, v[1] / norm, v[2] / norm])
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn mul(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+fn transpose(m: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    [
+        [m[0][0], m[1][0], m[2][0]],
+        [m[0][1], m[1][1], m[2][1]],
+        [m[0][2], m[1][2], m[2][2]],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    /// Asserts that both quaternions describe the same rotation, `q` and
+    /// `-q` being equivalent
+    fn assert_same_rotation(actual: Quaternion, expected: Quaternion, tolerance: f32) {
+        let sign = if actual.w * expected.w
+            + actual.x * expected.x
+            + actual.y * expected.y
+            + actual.z * expected.z
+            < 0.0
+        {
+            -1.0
+        } else {
+            1.0
+        };
+        let actual = [actual.w, actual.x, actual.y, actual.z];
+        let expected = [expected.w, expected.x, expected.y, expected.z];
+        for (&a, &e) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (sign * a - e).abs() <= tolerance,
+                "{:?} is not the rotation {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    /// Rotation by `angle` degrees around `axis`
+    fn axis_angle(axis: [f32; 3], angle: f32) -> Quaternion {
+        let [x, y, z] = normalize(axis).unwrap();
+        let (sin, cos) = (
+            libm::sinf(angle.to_radians() / 2.0),
+            libm::cosf(angle.to_radians() / 2.0),
+        );
+        Quaternion {
+            w: cos,
+            x: x * sin,
+            y: y * sin,
+            z: z * sin,
+        }
+    }
+
+    fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Quaternion {
+        axis_angle([0.0, 0.0, 1.0], yaw)
+            * axis_angle([0.0, 1.0, 0.0], pitch)
+            * axis_angle([1.0, 0.0, 0.0], roll)
+    }
+
+    /// Magnetic dip of the earth field, pointing down to the north
+    const DIP: f32 = 60.0;
+
+    /// Accelerometer and magnetometer readings of a board at rest in the
+    /// orientation `q`
+    fn sensors(q: Quaternion) -> (F32x3, F32x3) {
+        let r = q.rotation_matrix();
+        let earth = [
+            libm::cosf(DIP.to_radians()),
+            0.0,
+            -libm::sinf(DIP.to_radians()),
+        ];
+        let [mx, my, mz] = mul(&transpose(&r), earth);
+        (
+            F32x3::new(r[2][0], r[2][1], r[2][2]),
+            F32x3::new(mx, my, mz),
+        )
+    }
+
+    fn truth() -> Quaternion {
+        from_euler(60.0, 20.0, -30.0)
+    }
+
+    #[test]
+    fn rotation_matrix_round_trip() {
+        // One rotation per branch of Shepperd's method: positive trace, then
+        // r[0][0], r[1][1] and r[2][2] the largest diagonal element
+        let cases = [
+            ([1.0, 2.0, 3.0], 30.0),
+            ([1.0, 0.2, -0.1], 170.0),
+            ([0.2, 1.0, 0.1], 170.0),
+            ([-0.1, 0.2, 1.0], 170.0),
+        ];
+
+        for (branch, &(axis, angle)) in cases.iter().enumerate() {
+            let q = axis_angle(axis, angle);
+            let r = q.rotation_matrix();
+
+            let trace = r[0][0] + r[1][1] + r[2][2];
+            let largest = (0..3)
+                .max_by(|&i, &j| r[i][i].partial_cmp(&r[j][j]).unwrap())
+                .unwrap();
+            if branch == 0 {
+                assert!(trace > 0.0);
+            } else {
+                assert!(trace <= 0.0);
+                assert_eq!(largest, branch - 1);
+            }
+
+            let restored = Quaternion::from_rotation_matrix(&r);
+            assert_same_rotation(restored, q, 1e-5);
+            for (row, expected) in restored.rotation_matrix().iter().zip(r.iter()) {
+                for (&actual, &expected) in row.iter().zip(expected.iter()) {
+                    assert_close(actual, expected, 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn euler_angles_clamp_pitch_at_90_degrees() {
+        let euler = truth().to_euler();
+        assert_close(euler.yaw, 60.0, 1e-3);
+        assert_close(euler.pitch, 20.0, 1e-3);
+        assert_close(euler.roll, -30.0, 1e-3);
+
+        for &pitch in &[90.0, -90.0] {
+            // asin is steep next to ±1, so a rounding error of the sine
+            // shows as a few hundredths of a degree
+            let q = from_euler(0.0, pitch, 0.0);
+            assert_close(q.to_euler().pitch, pitch, 0.05);
+
+            // Slightly off unit length pushes the sine beyond ±1
+            let q = Quaternion {
+                w: q.w * 1.001,
+                x: q.x * 1.001,
+                y: q.y * 1.001,
+                z: q.z * 1.001,
+            };
+            assert_eq!(q.to_euler().pitch, pitch);
+        }
+    }
+
+    /// Runs `filter` at rest in the truth orientation from the identity
+    fn settle<F: Filter>(mut filter: F, with_field: bool) -> Quaternion {
+        let (accel, field) = sensors(truth());
+        let accel = [accel.x, accel.y, accel.z];
+        let field = if with_field {
+            Some([field.x, field.y, field.z])
+        } else {
+            None
+        };
+
+        // Mahony corrects the yaw slowly when the field is steep
+        let mut q = Quaternion::IDENTITY;
+        for _ in 0..20000 {
+            filter.update(&mut q, [0.0; 3], accel, field, 0.01);
+        }
+        q
+    }
+
+    #[test]
+    fn filters_converge_at_rest() {
+        let up = truth().rotation_matrix()[2];
+        for q in [
+            settle(Madgwick::default(), false),
+            settle(Mahony::default(), false),
+        ]
+        .iter()
+        {
+            // Without the field only the tilt is observable
+            for (&actual, &expected) in q.rotation_matrix()[2].iter().zip(up.iter()) {
+                assert_close(actual, expected, 2e-3);
+            }
+        }
+
+        assert_same_rotation(settle(Madgwick::default(), true), truth(), 2e-3);
+        assert_same_rotation(settle(Mahony::default(), true), truth(), 2e-3);
+    }
+
+    /// Turns at a constant 30 dps for 2 s from a level board
+    fn turn<F: Filter>(filter: F) -> Quaternion {
+        let mut ahrs = Ahrs::new(filter, 100.0);
+        let up = F32x3::new(0.0, 0.0, 1.0);
+        ahrs.update(F32x3::new(0.0, 0.0, 0.0), up, None);
+        for _ in 0..200 {
+            ahrs.update(F32x3::new(10.0, 20.0, 20.0), up, None);
+        }
+        ahrs.quaternion()
+    }
+
+    #[test]
+    fn constant_rate_matches_analytic_rotation() {
+        // Without feedback both filters only integrate the rate
+        let expected = axis_angle([1.0, 2.0, 2.0], 60.0);
+        assert_same_rotation(turn(Madgwick::new(0.0)), expected, 1e-4);
+        assert_same_rotation(turn(Mahony::new(0.0, 0.0)), expected, 1e-4);
+    }
+
+    #[test]
+    fn first_update_sets_orientation() {
+        let (accel, field) = sensors(truth());
+        let still = F32x3::new(0.0, 0.0, 0.0);
+
+        let mut ahrs = Ahrs::new(Madgwick::default(), 100.0);
+        assert_eq!(ahrs.quaternion(), Quaternion::IDENTITY);
+        ahrs.update(still, still, Some(field));
+        assert_eq!(ahrs.quaternion(), Quaternion::IDENTITY);
+        ahrs.update(still, accel, Some(field));
+        assert_same_rotation(ahrs.quaternion(), truth(), 1e-5);
+
+        // Without a usable field, north is the board x axis
+        for field in [None, Some(still)].iter() {
+            let mut ahrs = Ahrs::new(Madgwick::default(), 100.0);
+            ahrs.update(still, accel, *field);
+            let euler = ahrs.euler();
+            assert_close(euler.yaw, 0.0, 1e-3);
+            assert_close(euler.pitch, 20.0, 1e-3);
+            assert_close(euler.roll, -30.0, 1e-3);
+        }
+    }
+
+    #[test]
+    fn heading_follows_top_edge() {
+        // Level board, the top edge is 90° counterclockwise from x
+        for &(yaw, heading) in &[(0.0, 270.0), (-80.0, 350.0), (180.0, 90.0), (-135.0, 45.0)] {
+            let (accel, field) = sensors(from_euler(yaw, 0.0, 0.0));
+            let mut ahrs = Ahrs::new(Madgwick::default(), 100.0);
+            ahrs.update(F32x3::new(0.0, 0.0, 0.0), accel, Some(field));
+            assert_close(ahrs.heading(), heading, 1e-3);
+        }
+
+        // Tilted, it matches the tilt-compensated compass
+        let (accel, field) = sensors(truth());
+        let mut ahrs = Ahrs::new(Madgwick::default(), 100.0);
+        ahrs.update(F32x3::new(0.0, 0.0, 0.0), accel, Some(field));
+        let expected = crate::heading::tilt_compensated_heading(accel, field).unwrap();
+        assert_close(ahrs.heading(), expected, 1e-3);
+    }
+
+    #[test]
+    fn linear_acceleration_removes_gravity() {
+        let (accel, field) = sensors(truth());
+        let still = F32x3::new(0.0, 0.0, 0.0);
+
+        let mut ahrs = Ahrs::new(Madgwick::new(0.0), 100.0);
+        ahrs.update(still, accel, Some(field));
+        let linear = ahrs.linear_acceleration();
+        for &value in &[linear.x, linear.y, linear.z] {
+            assert_close(value, 0.0, 1e-5);
+        }
+
+        let push = [0.2, -0.1, 0.05];
+        let moving = F32x3::new(accel.x + push[0], accel.y + push[1], accel.z + push[2]);
+        ahrs.update(still, moving, Some(field));
+        let linear = ahrs.linear_acceleration();
+        for (&actual, &expected) in [linear.x, linear.y, linear.z].iter().zip(push.iter()) {
+            assert_close(actual, expected, 1e-5);
+        }
+    }
+
+    /// Inputs of the reference run at step `k`: rates in rad/s and unit
+    /// acceleration and field that disagree with the integrated rates
+    fn reference_inputs(k: u32) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let t = k as f32 * 0.01;
+        let gyro = [0.4 * libm::sinf(0.7 * t), -0.3 * libm::cosf(0.5 * t), 0.2];
+        let accel = normalize([0.2 * libm::sinf(0.3 * t), -0.1, 1.0]).unwrap();
+        let field = normalize([0.25, 0.05 * libm::cosf(0.2 * t), -0.4]).unwrap();
+        (gyro, accel, field)
+    }
+
+    /// Quaternions after 100, 300 and 600 steps
+    fn reference_run<F: Filter>(filter: &mut F, with_field: bool) -> [Quaternion; 3] {
+        let mut q = Quaternion::IDENTITY;
+        let mut checkpoints = [Quaternion::IDENTITY; 3];
+        for k in 1..=600 {
+            let (gyro, accel, field) = reference_inputs(k);
+            let field = if with_field { Some(field) } else { None };
+            filter.update(&mut q, gyro, accel, field, 0.01);
+            match k {
+                100 => checkpoints[0] = q,
+                300 => checkpoints[1] = q,
+                600 => checkpoints[2] = q,
+                _ => {}
+            }
+        }
+        checkpoints
+    }
+
+    fn assert_reference(actual: [Quaternion; 3], expected: [[f32; 4]; 3]) {
+        for (q, &[w, x, y, z]) in actual.iter().zip(expected.iter()) {
+            assert_same_rotation(*q, Quaternion { w, x, y, z }, 1e-3);
+        }
+    }
+
+    #[test]
+    fn filters_match_reference_implementations() {
+        // Recorded from ports of Madgwick's MadgwickAHRS.m and MahonyAHRS.c,
+        // run in double precision on the same inputs. MadgwickAHRS.c is not
+        // used for the 9-DoF run, it weighs the field half as much as the
+        // paper does.
+        assert_reference(
+            reference_run(&mut Madgwick::new(0.1), false),
+            [
+                [0.991056, -0.003827, -0.075223, 0.110161],
+                [0.915260, 0.178159, -0.094355, 0.348793],
+                [0.761465, -0.090181, -0.076177, 0.637366],
+            ],
+        );
+        assert_reference(
+            reference_run(&mut Madgwick::new(0.1), true),
+            [
+                [0.992285, -0.015809, -0.098105, 0.074131],
+                [0.942224, 0.166931, -0.162207, 0.240908],
+                [0.890394, -0.157813, -0.025886, 0.426173],
+            ],
+        );
+        assert_reference(
+            reference_run(&mut Mahony::new(0.5, 0.0), false),
+            [
+                [0.986763, 0.039068, -0.116697, 0.105611],
+                [0.899195, 0.218797, -0.131989, 0.355184],
+                [0.744274, -0.097241, -0.048278, 0.658991],
+            ],
+        );
+
+        let mut mahony = Mahony::new(0.5, 0.1);
+        assert_reference(
+            reference_run(&mut mahony, true),
+            [
+                [0.989627, 0.009196, -0.116637, 0.083369],
+                [0.968775, 0.066771, -0.138476, 0.194528],
+                [0.918347, -0.276944, 0.033422, 0.280756],
+            ],
+        );
+        let integral = mahony.integral();
+        for (&actual, &expected) in integral.iter().zip([-0.136644, 0.013634, -0.074910].iter()) {
+            assert_close(actual, expected, 1e-3);
+        }
+    }
+}
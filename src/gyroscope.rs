@@ -1,15 +1,24 @@
-use l3gd20;
+//! L3GD20 / I3G4250D gyroscope on SPI1
 
+use core::fmt::Debug;
+
+use accelerometer::vector::{F32x3, I16x3};
+
+use crate::calibration::{BiasEstimator, GyroCalibration};
 use crate::hal::gpio;
+use crate::hal::gpio::gpioa;
+use crate::hal::gpio::gpioe;
 use crate::hal::prelude::*;
 use crate::hal::rcc;
 use crate::hal::spi;
 use crate::hal::stm32;
+use crate::revision::GyroscopeVariant;
 
-use embedded_hal;
+use embedded_hal::blocking::spi::{Transfer, Write};
 use embedded_hal::digital::v2::OutputPin;
 
-type Spi1 = spi::Spi<
+/// SPI1 bus as wired to the gyroscope (PA5 SCK, PA6 MISO, PA7 MOSI)
+pub type Spi1 = spi::Spi<
     stm32::SPI1,
     (
         gpioa::PA5<gpio::Alternate<gpio::AF5>>,
@@ -18,41 +27,746 @@ type Spi1 = spi::Spi<
     ),
 >;
 
-type ChipSelect = gpioe::PE3<gpio::Output<gpio::PushPull>>;
+/// Chip select of the gyroscope
+pub type ChipSelect = gpioe::PE3<gpio::Output<gpio::PushPull>>;
+
+/// Register address bit selecting a read access
+const READ: u8 = 0x80;
+
+/// Register address bit enabling auto-increment on multi-byte accesses
+const AUTO_INCREMENT: u8 = 0x40;
+
+/// Number of samples the FIFO holds
+pub const FIFO_DEPTH: usize = 32;
 
-pub struct Gyroscope {
-    l3gd20: l3gd20::l3gd20<Spi1, ChipSelect>,
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+enum Register {
+    WHO_AM_I = 0x0F,
+    CTRL_REG1 = 0x20,
+    CTRL_REG3 = 0x22,
+    CTRL_REG4 = 0x23,
+    CTRL_REG5 = 0x24,
+    OUT_TEMP = 0x26,
+    STATUS_REG = 0x27,
+    OUT_X_L = 0x28,
+    FIFO_CTRL_REG = 0x2E,
+    FIFO_SRC_REG = 0x2F,
+    INT1_CFG = 0x30,
+    INT1_SRC = 0x31,
+    INT1_TSH_XH = 0x32,
+    INT1_TSH_XL = 0x33,
+    INT1_TSH_YH = 0x34,
+    INT1_TSH_YL = 0x35,
+    INT1_TSH_ZH = 0x36,
+    INT1_TSH_ZL = 0x37,
+    INT1_DURATION = 0x38,
+}
+
+/// Gyroscope errors
+#[derive(Debug)]
+pub enum Error<E> {
+    /// SPI bus error
+    Spi(E),
+    /// WHO_AM_I returned an unexpected device identifier
+    UnknownDevice(u8),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Error::Spi(error)
+    }
 }
 
-impl Gyroscope {
+/// Gyroscope full scale range
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FullScale {
+    /// ±250 dps (±245 dps on the I3G4250D)
+    Dps250 = 0b00,
+    /// ±500 dps
+    Dps500 = 0b01,
+    /// ±2000 dps
+    Dps2000 = 0b10,
+}
+
+impl FullScale {
+    /// Sensitivity in dps/LSB
+    fn sensitivity(self) -> f32 {
+        match self {
+            FullScale::Dps250 => 0.00875,
+            FullScale::Dps500 => 0.0175,
+            FullScale::Dps2000 => 0.07,
+        }
+    }
+}
+
+/// Gyroscope output data rate
+///
+/// The variants are named after the L3GD20 rates, the I3G4250D runs about
+/// 10 % faster with the same setting. See [`Gyroscope::sample_rate`] for the
+/// actual rate of the fitted part.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DataRate {
+    /// 95 Hz, 105 Hz on the I3G4250D
+    Hz95 = 0b00,
+    /// 190 Hz, 208 Hz on the I3G4250D
+    Hz190 = 0b01,
+    /// 380 Hz, 420 Hz on the I3G4250D
+    Hz380 = 0b10,
+    /// 760 Hz, 840 Hz on the I3G4250D
+    Hz760 = 0b11,
+}
+
+impl DataRate {
+    /// Output data rate in Hz on the L3GD20
+    pub fn hz(self) -> f32 {
+        match self {
+            DataRate::Hz95 => 95.0,
+            DataRate::Hz190 => 190.0,
+            DataRate::Hz380 => 380.0,
+            DataRate::Hz760 => 760.0,
+        }
+    }
+
+    /// Output data rate in Hz on the given part
+    pub fn hz_on(self, variant: GyroscopeVariant) -> f32 {
+        match variant {
+            GyroscopeVariant::L3gd20 => self.hz(),
+            GyroscopeVariant::I3g4250d => match self {
+                DataRate::Hz95 => 105.0,
+                DataRate::Hz190 => 208.0,
+                DataRate::Hz380 => 420.0,
+                DataRate::Hz760 => 840.0,
+            },
+        }
+    }
+}
+
+/// Low-pass filter bandwidth selection
+///
+/// The resulting cut-off frequency depends on the output data rate, see
+/// [`Bandwidth::cutoff`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bandwidth {
+    /// Lowest cut-off frequency
+    Low = 0b00,
+    /// Medium-low cut-off frequency
+    Medium = 0b01,
+    /// Medium-high cut-off frequency
+    High = 0b10,
+    /// Highest cut-off frequency
+    Maximum = 0b11,
+}
+
+impl Bandwidth {
+    /// Cut-off frequency in Hz for the given output data rate
+    ///
+    /// These are the L3GD20 values. The I3G4250D matches them except for
+    /// `Maximum` at its two fastest rates, where it cuts off at 110 Hz.
+    pub fn cutoff(self, data_rate: DataRate) -> f32 {
+        let table = match data_rate {
+            DataRate::Hz95 => [12.5, 25.0, 25.0, 25.0],
+            DataRate::Hz190 => [12.5, 25.0, 50.0, 70.0],
+            DataRate::Hz380 => [20.0, 25.0, 50.0, 100.0],
+            DataRate::Hz760 => [30.0, 35.0, 50.0, 100.0],
+        };
+        table[self as usize]
+    }
+}
+
+/// FIFO operating mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FifoMode {
+    /// FIFO disabled, only the latest sample is kept
+    Bypass = 0b000,
+    /// Collects samples until full, then stops
+    Fifo = 0b001,
+    /// Collects samples, discarding the oldest one when full
+    Stream = 0b010,
+    /// Works as `Stream` until the INT1 event fires, then as `Fifo`
+    StreamToFifo = 0b011,
+    /// Works as `Bypass` until the INT1 event fires, then as `Stream`
+    BypassToStream = 0b100,
+}
+
+/// FIFO fill level and flags
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FifoStatus {
+    /// Number of unread samples, up to [`FIFO_DEPTH`]
+    pub len: usize,
+    /// The fill level has reached the watermark
+    pub watermark: bool,
+    /// The FIFO is full and, in stream mode, samples have been overwritten
+    pub overrun: bool,
+}
+
+/// Interrupt sources routed to INT2/DRDY, wired to PE1
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Interrupts {
+    /// New sample available
+    pub data_ready: bool,
+    /// FIFO watermark reached
+    pub fifo_watermark: bool,
+    /// FIFO overrun
+    pub fifo_overrun: bool,
+    /// FIFO empty
+    pub fifo_empty: bool,
+}
+
+/// How the per-axis rate events combine into an interrupt
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Combination {
+    /// Any enabled axis above its threshold
+    Or,
+    /// All enabled axes above their thresholds
+    And,
+}
+
+/// Angular rate threshold interrupt settings
+///
+/// The thresholds and duration are converted using the full scale range and
+/// data rate configured at the time they are written.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateInterruptConfig {
+    /// Threshold on the X axis rate, in dps, `None` ignores the axis
+    pub x_dps: Option<f32>,
+    /// Threshold on the Y axis rate, in dps, `None` ignores the axis
+    pub y_dps: Option<f32>,
+    /// Threshold on the Z axis rate, in dps, `None` ignores the axis
+    pub z_dps: Option<f32>,
+    /// Event combination
+    pub combination: Combination,
+    /// Minimum time the condition must hold, in ms
+    pub duration_ms: u16,
+    /// Keep INT1 active until the event is read
+    pub latch: bool,
+}
+
+impl RateInterruptConfig {
+    /// Wake-up on rotation: any axis above `threshold_dps`
+    pub fn wake_up(threshold_dps: f32) -> Self {
+        RateInterruptConfig {
+            x_dps: Some(threshold_dps),
+            y_dps: Some(threshold_dps),
+            z_dps: Some(threshold_dps),
+            combination: Combination::Or,
+            duration_ms: 0,
+            latch: true,
+        }
+    }
+}
+
+/// Axes whose rate exceeded the threshold when the interrupt fired
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RateEvent {
+    /// Rotation about the X axis
+    pub x: bool,
+    /// Rotation about the Y axis
+    pub y: bool,
+    /// Rotation about the Z axis
+    pub z: bool,
+}
+
+/// Raw angular rate sample with its acquisition time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimedSample {
+    /// Acquisition time, in ticks of the clock passed to
+    /// [`Gyroscope::read_fifo_timed`]
+    pub timestamp: u32,
+    /// Raw angular rate, see [`Gyroscope::to_dps`]
+    pub rate: I16x3,
+}
+
+impl Default for TimedSample {
+    fn default() -> Self {
+        TimedSample {
+            timestamp: 0,
+            rate: I16x3::new(0, 0, 0),
+        }
+    }
+}
+
+/// On-board gyroscope
+pub struct Gyroscope<SPI = Spi1, CS = ChipSelect> {
+    spi: SPI,
+    cs: CS,
+    variant: GyroscopeVariant,
+    full_scale: FullScale,
+    data_rate: DataRate,
+    bandwidth: Bandwidth,
+    fifo_mode: FifoMode,
+    watermark: u8,
+    calibration: Option<GyroCalibration>,
+    bias_estimator: Option<BiasEstimator>,
+    temperature: i8,
+}
+
+impl Gyroscope<Spi1, ChipSelect> {
+    /// Configures SPI1 on PA5/PA6/PA7 with PE3 as chip select and initializes
+    /// the gyroscope
     pub fn new(
-        gpioa: gpioa::Parts,
-        gpioe: gpioe::Parts,
+        sck: gpioa::PA5<gpio::Input<gpio::Floating>>,
+        miso: gpioa::PA6<gpio::Input<gpio::Floating>>,
+        mosi: gpioa::PA7<gpio::Input<gpio::Floating>>,
+        cs: gpioe::PE3<gpio::Input<gpio::Floating>>,
         spi1: stm32::SPI1,
         clocks: rcc::Clocks,
-    )-> Self {
-        let sck = gpioa.pa5.into_alternate_af5().internal_pull_up(false);
-        let miso = gpioa.pa6.into_alternate_af5().internal_pull_up(false);
-        let mosi = gpioa.pa7.into_alternate_af5().internal_pull_up(false);
+    ) -> Result<Self, Error<spi::Error>> {
+        let sck = sck.into_alternate_af5().internal_pull_up(false);
+        let miso = miso.into_alternate_af5().internal_pull_up(false);
+        let mosi = mosi.into_alternate_af5().internal_pull_up(false);
 
         let spi_mode = spi::Mode {
-            polarity: spi::Polarity::IdleLow,
-            phase: spi::Phase::CaptureOnFirstTransition,
+            polarity: spi::Polarity::IdleHigh,
+            phase: spi::Phase::CaptureOnSecondTransition,
         };
 
-        let spi = spi::Spi::spi1(spi1, (sck,miso,mosi),spi_mode, 10.mhz().into(), clocks);
+        let spi = spi::Spi::spi1(spi1, (sck, miso, mosi), spi_mode, 10.mhz().into(), clocks);
 
-        let mut chip_select = gpioe.pe3.into_push_pull_output();
-        chip_select.set_high().ok();
+        let chip_select = cs.into_push_pull_output();
 
-        let config = l3gd20::Config {
-            scale: l3gd20::Scale::PlusMinus8G,
-            ..Default::default()
+        Self::from_spi(spi, chip_select)
+    }
+}
+
+impl<SPI, CS, E> Gyroscope<SPI, CS>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    E: Debug,
+{
+    /// Initializes the gyroscope on an already configured SPI bus
+    ///
+    /// The part is identified through its WHO_AM_I register, then powered up
+    /// at 95 Hz (105 Hz on the I3G4250D), ±250 dps with the lowest filter
+    /// bandwidth.
+    pub fn from_spi(spi: SPI, mut cs: CS) -> Result<Self, Error<E>> {
+        cs.set_high().ok();
+
+        let mut gyroscope = Self {
+            spi,
+            cs,
+            variant: GyroscopeVariant::L3gd20,
+            full_scale: FullScale::Dps250,
+            data_rate: DataRate::Hz95,
+            bandwidth: Bandwidth::Low,
+            fifo_mode: FifoMode::Bypass,
+            watermark: 0,
+            calibration: None,
+            bias_estimator: None,
+            temperature: 0,
         };
 
-        let l3gd20 = l3gd20::l3gd20::new(spi, chip_select, config);
+        let id = gyroscope.read_register(Register::WHO_AM_I)?;
+        gyroscope.variant = GyroscopeVariant::from_id(id).ok_or(Error::UnknownDevice(id))?;
+
+        gyroscope.write_ctrl_reg1()?;
+        // BDU, little endian, ±250 dps
+        gyroscope.write_register(Register::CTRL_REG4, 0x80)?;
 
-        Self{ l3gd20 }
+        Ok(gyroscope)
+    }
+
+    /// Releases the SPI bus and the chip select pin
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    /// Returns the gyroscope part this driver talks to
+    pub fn variant(&self) -> GyroscopeVariant {
+        self.variant
+    }
+
+    /// Sets the full scale range
+    pub fn set_full_scale(&mut self, full_scale: FullScale) -> Result<(), Error<E>> {
+        self.modify_register(Register::CTRL_REG4, |r| {
+            (r & !0b0011_0000) | ((full_scale as u8) << 4)
+        })?;
+        self.full_scale = full_scale;
+        Ok(())
+    }
+
+    /// Returns the configured full scale range
+    pub fn full_scale(&self) -> FullScale {
+        self.full_scale
     }
-}
 
+    /// Sets the output data rate
+    pub fn set_data_rate(&mut self, data_rate: DataRate) -> Result<(), Error<E>> {
+        self.data_rate = data_rate;
+        self.write_ctrl_reg1()
+    }
+
+    /// Returns the configured output data rate
+    pub fn data_rate(&self) -> DataRate {
+        self.data_rate
+    }
+
+    /// Returns the actual output data rate of the fitted part in Hz
+    pub fn sample_rate(&self) -> f32 {
+        self.data_rate.hz_on(self.variant)
+    }
+
+    /// Sets the low-pass filter bandwidth
+    pub fn set_bandwidth(&mut self, bandwidth: Bandwidth) -> Result<(), Error<E>> {
+        self.bandwidth = bandwidth;
+        self.write_ctrl_reg1()
+    }
+
+    /// Returns the configured low-pass filter bandwidth
+    pub fn bandwidth(&self) -> Bandwidth {
+        self.bandwidth
+    }
+
+    /// Returns `true` if a new sample is available on all axes
+    pub fn data_ready(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.read_register(Register::STATUS_REG)? & 0x08 != 0)
+    }
+
+    /// Reads the raw angular rate sample
+    pub fn gyro_raw(&mut self) -> Result<I16x3, Error<E>> {
+        let mut buffer = [0u8; 7];
+        buffer[0] = Register::OUT_X_L as u8 | READ | AUTO_INCREMENT;
+        self.transfer(&mut buffer)?;
+
+        Ok(I16x3::new(
+            i16::from_le_bytes([buffer[1], buffer[2]]),
+            i16::from_le_bytes([buffer[3], buffer[4]]),
+            i16::from_le_bytes([buffer[5], buffer[6]]),
+        ))
+    }
+
+    /// Reads the angular rate in degrees per second, with the calibration
+    /// applied
+    ///
+    /// The die temperature is read along with the rate while the bias is
+    /// tracked or temperature compensated. While tracking, the reading also
+    /// feeds the [`BiasEstimator`], whose updates take effect immediately.
+    pub fn gyro_dps(&mut self) -> Result<F32x3, Error<E>> {
+        let raw = self.gyro_raw()?;
+        let rate = self.to_uncalibrated_dps(raw);
+
+        let compensated = self
+            .calibration
+            .map_or(false, |c| c.temperature_coefficient != [0.0; 3]);
+        if self.bias_estimator.is_some() || compensated {
+            self.temperature()?;
+        }
+
+        if let Some(estimator) = self.bias_estimator.as_mut() {
+            if let Some(calibration) = estimator.update(rate, self.temperature) {
+                self.calibration = Some(calibration);
+            }
+        }
+
+        Ok(self.calibrate(rate))
+    }
+
+    /// Converts a raw sample to degrees per second in the configured range,
+    /// with the calibration applied
+    ///
+    /// The temperature drift is compensated for the last temperature read.
+    pub fn to_dps(&self, sample: I16x3) -> F32x3 {
+        self.calibrate(self.to_uncalibrated_dps(sample))
+    }
+
+    /// Sets the calibration applied to readings in dps, `None` to disable it
+    ///
+    /// See [`GyroCalibration`](crate::calibration::GyroCalibration).
+    pub fn set_calibration(&mut self, calibration: Option<GyroCalibration>) {
+        self.calibration = calibration;
+    }
+
+    /// Returns the calibration in use, including the updates of the bias
+    /// estimator
+    pub fn calibration(&self) -> Option<GyroCalibration> {
+        self.calibration
+    }
+
+    /// Starts tracking the zero-rate level in [`Gyroscope::gyro_dps`]
+    pub fn enable_bias_tracking(&mut self, estimator: BiasEstimator) {
+        self.calibration = Some(estimator.calibration());
+        self.bias_estimator = Some(estimator);
+    }
+
+    /// Stops tracking the zero-rate level and returns the estimator
+    ///
+    /// The latest calibration stays in use.
+    pub fn disable_bias_tracking(&mut self) -> Option<BiasEstimator> {
+        self.bias_estimator.take()
+    }
+
+    fn to_uncalibrated_dps(&self, sample: I16x3) -> F32x3 {
+        let sensitivity = self.full_scale.sensitivity();
+
+        F32x3::new(
+            sample.x as f32 * sensitivity,
+            sample.y as f32 * sensitivity,
+            sample.z as f32 * sensitivity,
+        )
+    }
+
+    fn calibrate(&self, rate: F32x3) -> F32x3 {
+        match self.calibration {
+            Some(calibration) => calibration.apply(rate, self.temperature),
+            None => rate,
+        }
+    }
+
+    /// Routes interrupt sources to INT2/DRDY
+    ///
+    /// Replaces any previous routing of that pin. Use
+    /// [`InterruptLine`](crate::exti::InterruptLine) with PE1 to get the
+    /// EXTI1 interrupt.
+    pub fn route_interrupts(&mut self, interrupts: Interrupts) -> Result<(), Error<E>> {
+        let value = ((interrupts.data_ready as u8) << 3)
+            | ((interrupts.fifo_watermark as u8) << 2)
+            | ((interrupts.fifo_overrun as u8) << 1)
+            | (interrupts.fifo_empty as u8);
+        self.modify_register(Register::CTRL_REG3, |r| (r & !0b0000_1111) | value)
+    }
+
+    /// Configures the FIFO mode and watermark level
+    ///
+    /// The watermark flag is raised once `watermark` samples are pending,
+    /// `watermark` is clamped to 31. A FIFO stopped in `Fifo` mode is
+    /// restarted by going through `Bypass`.
+    pub fn set_fifo_mode(&mut self, mode: FifoMode, watermark: u8) -> Result<(), Error<E>> {
+        let fifo_en = (mode != FifoMode::Bypass) as u8;
+
+        self.modify_register(Register::CTRL_REG5, |r| (r & !0b0100_0000) | (fifo_en << 6))?;
+        self.write_register(
+            Register::FIFO_CTRL_REG,
+            ((mode as u8) << 5) | watermark.min(31),
+        )?;
+        self.fifo_mode = mode;
+        self.watermark = watermark.min(31);
+        Ok(())
+    }
+
+    /// Returns the configured FIFO mode
+    pub fn fifo_mode(&self) -> FifoMode {
+        self.fifo_mode
+    }
+
+    /// Reads the FIFO fill level and flags
+    pub fn fifo_status(&mut self) -> Result<FifoStatus, Error<E>> {
+        let src = self.read_register(Register::FIFO_SRC_REG)?;
+        let overrun = src & 0b0100_0000 != 0;
+
+        Ok(FifoStatus {
+            // FSS saturates at 31 with the overrun flag set once full
+            len: if overrun {
+                FIFO_DEPTH
+            } else {
+                (src & 0b0001_1111) as usize
+            },
+            watermark: src & 0b1000_0000 != 0,
+            overrun,
+        })
+    }
+
+    /// Reads all pending samples, up to the length of `samples`, in a single
+    /// SPI transaction
+    ///
+    /// Samples are returned oldest first. In `Bypass` mode, the latest
+    /// sample is read if [`data_ready`](Self::data_ready) is set. Returns the
+    /// number of samples read.
+    pub fn read_fifo(&mut self, samples: &mut [I16x3]) -> Result<usize, Error<E>> {
+        let count = if self.fifo_mode == FifoMode::Bypass {
+            self.data_ready()? as usize
+        } else {
+            self.fifo_status()?.len
+        };
+        let count = count.min(samples.len());
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // With the FIFO enabled, the auto-increment wraps from OUT_Z_H back
+        // to OUT_X_L, popping one sample per six bytes
+        let mut buffer = [0u8; 1 + 6 * FIFO_DEPTH];
+        let buffer = &mut buffer[..1 + 6 * count];
+        buffer[0] = Register::OUT_X_L as u8 | READ | AUTO_INCREMENT;
+        self.transfer(buffer)?;
+
+        for (sample, bytes) in samples.iter_mut().zip(buffer[1..].chunks(6)) {
+            *sample = I16x3::new(
+                i16::from_le_bytes([bytes[0], bytes[1]]),
+                i16::from_le_bytes([bytes[2], bytes[3]]),
+                i16::from_le_bytes([bytes[4], bytes[5]]),
+            );
+        }
+
+        Ok(count)
+    }
+
+    /// Reads all pending samples like [`read_fifo`](Self::read_fifo) and
+    /// timestamps them
+    ///
+    /// `timestamp` comes from a free running clock of `clock_hz` such as the
+    /// DWT cycle counter, usually captured in the EXTI1 handler. With the
+    /// FIFO enabled and a watermark set, it is taken as the time of the
+    /// sample that reached the watermark, i.e. the sample at index
+    /// `watermark - 1`, since more samples may arrive before the handler
+    /// reads the FIFO. Otherwise it is the time of the newest sample. The
+    /// other samples are stamped one output data period apart, wrapping like
+    /// the clock.
+    ///
+    /// The FIFO must have been drained at the previous watermark interrupt
+    /// for the anchor to hold.
+    pub fn read_fifo_timed(
+        &mut self,
+        timestamp: u32,
+        clock_hz: u32,
+        samples: &mut [TimedSample],
+    ) -> Result<usize, Error<E>> {
+        let mut rates = [I16x3::new(0, 0, 0); FIFO_DEPTH];
+        let len = samples.len().min(FIFO_DEPTH);
+        let count = self.read_fifo(&mut rates[..len])?;
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // The watermark interrupt fires as the sample at `watermark - 1`
+        // arrives
+        let anchor = if self.fifo_mode == FifoMode::Bypass || self.watermark == 0 {
+            count - 1
+        } else {
+            usize::from(self.watermark - 1).min(count - 1)
+        };
+
+        let period = clock_hz as f32 / self.sample_rate();
+        for (i, (sample, rate)) in samples.iter_mut().zip(rates[..count].iter()).enumerate() {
+            let timestamp = if i < anchor {
+                timestamp.wrapping_sub(((anchor - i) as f32 * period) as u32)
+            } else {
+                timestamp.wrapping_add(((i - anchor) as f32 * period) as u32)
+            };
+            *sample = TimedSample {
+                timestamp,
+                rate: *rate,
+            };
+        }
+
+        Ok(count)
+    }
+
+    /// Reads the die temperature register
+    ///
+    /// The value is uncalibrated and decreases by 1 LSB per °C, so it is only
+    /// useful to track temperature changes.
+    pub fn temperature(&mut self) -> Result<i8, Error<E>> {
+        self.temperature = self.read_register(Register::OUT_TEMP)? as i8;
+        Ok(self.temperature)
+    }
+
+    /// Configures the angular rate threshold interrupt and routes it to INT1
+    ///
+    /// INT1 is wired to PE0, which shares EXTI0 with the user button: an
+    /// [`InterruptLine`](crate::exti::InterruptLine) on PE0 takes the line
+    /// over from PA0. Read the events with [`Gyroscope::rate_event`].
+    pub fn enable_rate_interrupt(&mut self, config: RateInterruptConfig) -> Result<(), Error<E>> {
+        let thresholds = [
+            (Register::INT1_TSH_XH, Register::INT1_TSH_XL, config.x_dps),
+            (Register::INT1_TSH_YH, Register::INT1_TSH_YL, config.y_dps),
+            (Register::INT1_TSH_ZH, Register::INT1_TSH_ZL, config.z_dps),
+        ];
+
+        // High events only: XHIE, YHIE and ZHIE are bits 1, 3 and 5
+        let mut value = 0;
+        for (i, &(high, low, dps)) in thresholds.iter().enumerate() {
+            if let Some(dps) = dps {
+                let lsb = dps.abs() / self.full_scale.sensitivity();
+                let threshold = if lsb >= 32767.0 { 0x7FFF } else { lsb as u16 };
+                let [h, l] = threshold.to_be_bytes();
+                self.write_register(high, h)?;
+                self.write_register(low, l)?;
+                value |= 0b10 << (2 * i);
+            }
+        }
+        if config.combination == Combination::And {
+            value |= 0b1000_0000;
+        }
+        if config.latch {
+            value |= 0b0100_0000;
+        }
+
+        // WAIT holds the interrupt for the duration after the condition ends
+        let duration = self.ms_to_periods(config.duration_ms).min(0x7F);
+        let wait = if duration > 0 { 0b1000_0000 } else { 0 };
+        self.write_register(Register::INT1_DURATION, wait | duration)?;
+        self.write_register(Register::INT1_CFG, value)?;
+
+        self.modify_register(Register::CTRL_REG3, |r| r | 0b1000_0000)
+    }
+
+    /// Disables the angular rate threshold interrupt and its routing to INT1
+    pub fn disable_rate_interrupt(&mut self) -> Result<(), Error<E>> {
+        self.write_register(Register::INT1_CFG, 0)?;
+        self.modify_register(Register::CTRL_REG3, |r| r & !0b1000_0000)
+    }
+
+    /// Reads the state of the angular rate threshold interrupt
+    ///
+    /// Returns `None` if it has not fired. Reading also releases a latched
+    /// INT1.
+    pub fn rate_event(&mut self) -> Result<Option<RateEvent>, Error<E>> {
+        let src = self.read_register(Register::INT1_SRC)?;
+        if src & 0b0100_0000 == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(RateEvent {
+            x: src & 0b0000_0010 != 0,
+            y: src & 0b0000_1000 != 0,
+            z: src & 0b0010_0000 != 0,
+        }))
+    }
+
+    /// Number of output data rate periods in `ms`, saturating at 255
+    fn ms_to_periods(&self, ms: u16) -> u8 {
+        let periods = f32::from(ms) * self.sample_rate() / 1000.0 + 0.5;
+        if periods >= 255.0 {
+            255
+        } else {
+            periods as u8
+        }
+    }
+
+    fn write_ctrl_reg1(&mut self) -> Result<(), Error<E>> {
+        // Normal mode, X/Y/Z enabled
+        let value = ((self.data_rate as u8) << 6) | ((self.bandwidth as u8) << 4) | 0b1111;
+        self.write_register(Register::CTRL_REG1, value)
+    }
+
+    fn transfer(&mut self, buffer: &mut [u8]) -> Result<(), Error<E>> {
+        self.cs.set_low().ok();
+        let result = self.spi.transfer(buffer);
+        self.cs.set_high().ok();
+        result?;
+        Ok(())
+    }
+
+    fn read_register(&mut self, register: Register) -> Result<u8, Error<E>> {
+        let mut buffer = [register as u8 | READ, 0];
+        self.transfer(&mut buffer)?;
+        Ok(buffer[1])
+    }
+
+    fn write_register(&mut self, register: Register, value: u8) -> Result<(), Error<E>> {
+        self.cs.set_low().ok();
+        let result = self.spi.write(&[register as u8, value]);
+        self.cs.set_high().ok();
+        result?;
+        Ok(())
+    }
+
+    fn modify_register<F>(&mut self, register: Register, f: F) -> Result<(), Error<E>>
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        let value = self.read_register(register)?;
+        self.write_register(register, f(value))
+    }
+}
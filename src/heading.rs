@@ -0,0 +1,268 @@
+//! Tilt-compensated compass heading
+//!
+//! The heading is the angle, clockwise from north, of the direction the top
+//! (orange LED) edge of the board points to. Gravity from the accelerometer
+//! gives the horizontal plane the magnetic field is projected onto, so the
+//! heading stays correct while the board is tilted. Both dies of the
+//! e-compass share the same axes.
+
+use core::fmt::Debug;
+
+use accelerometer::vector::F32x3;
+
+use crate::accelerometer::Accelerometer;
+use crate::compass::Compass;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// Heading in degrees, from 0 to 360
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Heading {
+    /// Relative to magnetic north
+    pub magnetic: f32,
+    /// Relative to true north, using the configured declination
+    pub true_north: f32,
+}
+
+/// Heading of the top edge from gravity and magnetic field readings
+///
+/// Both readings may be in any unit. Returns `None` if either reading is
+/// zero or the field is vertical, which leaves the heading undefined.
+pub fn tilt_compensated_heading(gravity: F32x3, field: F32x3) -> Option<f32> {
+    let g = [gravity.x, gravity.y, gravity.z];
+    let m = [field.x, field.y, field.z];
+
+    let g_norm = libm::sqrtf(g[0] * g[0] + g[1] * g[1] + g[2] * g[2]);
+    if g_norm == 0.0 {
+        return None;
+    }
+    // The accelerometer at rest measures the reaction to gravity, pointing up
+    let up = [g[0] / g_norm, g[1] / g_norm, g[2] / g_norm];
+
+    // Horizontal field points north, east = north × up
+    let m_up = m[0] * up[0] + m[1] * up[1] + m[2] * up[2];
+    let north_y = m[1] - m_up * up[1];
+    let east_y = m[2] * up[0] - m[0] * up[2];
+    if north_y == 0.0 && east_y == 0.0 {
+        return None;
+    }
+
+    Some(normalize(libm::atan2f(east_y, north_y).to_degrees()))
+}
+
+/// Runs one step of the heading low-pass filter
+///
+/// The filter works on the heading as a unit vector `(sin, cos)`, so it
+/// moves the short way across north. Returns the new filter state and the
+/// filtered heading in degrees, from 0 to 360.
+fn smooth(state: Option<(f32, f32)>, heading: f32, smoothing: f32) -> ((f32, f32), f32) {
+    let heading = heading.to_radians();
+    let (sin, cos) = (libm::sinf(heading), libm::cosf(heading));
+    let (sin, cos) = match state {
+        Some((s, c)) => (s + smoothing * (sin - s), c + smoothing * (cos - c)),
+        None => (sin, cos),
+    };
+    ((sin, cos), normalize(libm::atan2f(sin, cos).to_degrees()))
+}
+
+/// Adds the declination to a magnetic heading
+fn with_declination(magnetic: f32, declination: f32) -> Heading {
+    Heading {
+        magnetic,
+        true_north: normalize(magnetic + declination),
+    }
+}
+
+/// Wraps an angle in degrees into 0..360
+fn normalize(angle: f32) -> f32 {
+    let angle = angle % 360.0;
+    if angle < 0.0 {
+        angle + 360.0
+    } else {
+        angle
+    }
+}
+
+/// Compass combining the accelerometer and the magnetometer
+pub struct TiltCompensatedCompass<I2C> {
+    accelerometer: Accelerometer<I2C>,
+    compass: Compass<I2C>,
+    declination: f32,
+    smoothing: f32,
+    filtered: Option<(f32, f32)>,
+}
+
+impl<I2C, E> TiltCompensatedCompass<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+    E: Debug,
+{
+    /// Creates a compass without declination and without smoothing
+    ///
+    /// Calibrations set on either driver are applied to the readings.
+    pub fn new(accelerometer: Accelerometer<I2C>, compass: Compass<I2C>) -> Self {
+        TiltCompensatedCompass {
+            accelerometer,
+            compass,
+            declination: 0.0,
+            smoothing: 1.0,
+            filtered: None,
+        }
+    }
+
+    /// Sets the magnetic declination in degrees, positive when magnetic
+    /// north lies east of true north
+    pub fn set_declination(&mut self, declination: f32) {
+        self.declination = declination;
+    }
+
+    /// Returns the magnetic declination in degrees
+    pub fn declination(&self) -> f32 {
+        self.declination
+    }
+
+    /// Sets the weight of a new reading in the heading low-pass filter, from
+    /// just above 0 (heavy smoothing) to 1 (no smoothing)
+    ///
+    /// The filter runs on the heading as a unit vector, so it moves through
+    /// the short way across north instead of sweeping back through south.
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing;
+    }
+
+    /// Reads both sensors and returns the smoothed heading
+    ///
+    /// Returns `None` while the heading is undefined, see
+    /// [`tilt_compensated_heading`].
+    pub fn read(&mut self) -> Result<Option<Heading>, E> {
+        let raw = self.accelerometer.read_sample()?;
+        let gravity = self.accelerometer.to_g(raw);
+        let field = self.compass.mag_gauss()?;
+
+        let heading = match tilt_compensated_heading(gravity, field) {
+            Some(heading) => heading,
+            None => return Ok(None),
+        };
+
+        let (state, magnetic) = smooth(self.filtered, heading, self.smoothing);
+        self.filtered = Some(state);
+        Ok(Some(with_declination(magnetic, self.declination)))
+    }
+
+    /// Returns the accelerometer, e.g. to change its configuration
+    pub fn accelerometer(&mut self) -> &mut Accelerometer<I2C> {
+        &mut self.accelerometer
+    }
+
+    /// Returns the magnetometer, e.g. to change its configuration
+    pub fn compass(&mut self) -> &mut Compass<I2C> {
+        &mut self.compass
+    }
+
+    /// Releases both drivers
+    pub fn release(self) -> (Accelerometer<I2C>, Compass<I2C>) {
+        (self.accelerometer, self.compass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    /// Distance between two headings in degrees, the short way round
+    fn distance(a: f32, b: f32) -> f32 {
+        let d = normalize(a - b);
+        d.min(360.0 - d)
+    }
+
+    #[test]
+    fn level_board_heading() {
+        let up = F32x3::new(0.0, 0.0, 1.0);
+        // Field pointing north and down, seen with the board turned so that
+        // the top (y) edge points to each heading
+        for &heading in &[0.0f32, 45.0, 90.0, 200.0, 315.0] {
+            let bearing = heading.to_radians();
+            // North in the board frame, y towards the heading
+            let field = F32x3::new(-libm::sinf(bearing) * 0.3, libm::cosf(bearing) * 0.3, -0.4);
+            let actual = tilt_compensated_heading(up, field).unwrap();
+            assert!(
+                distance(actual, heading) < 1e-3,
+                "{} for {}",
+                actual,
+                heading
+            );
+        }
+    }
+
+    #[test]
+    fn undefined_heading() {
+        let up = F32x3::new(0.0, 0.0, 1.0);
+        let field = F32x3::new(0.3, 0.0, -0.4);
+        let zero = F32x3::new(0.0, 0.0, 0.0);
+
+        assert_eq!(tilt_compensated_heading(zero, field), None);
+        assert_eq!(tilt_compensated_heading(up, zero), None);
+        // Field along gravity, no horizontal component
+        assert_eq!(
+            tilt_compensated_heading(up, F32x3::new(0.0, 0.0, -0.5)),
+            None
+        );
+        let tilted = F32x3::new(0.3, -0.2, 0.9);
+        let vertical = F32x3::new(-0.6, 0.4, -1.8);
+        assert_eq!(tilt_compensated_heading(tilted, vertical), None);
+    }
+
+    #[test]
+    fn smoothing_crosses_north_the_short_way() {
+        let (mut state, first) = smooth(None, 350.0, 0.3);
+        assert_close(first, 350.0, 1e-3);
+
+        let mut previous = first;
+        for _ in 0..50 {
+            let (next, heading) = smooth(Some(state), 10.0, 0.3);
+            state = next;
+
+            // Always within the 20° arc through north, moving towards 10°
+            assert!(
+                distance(heading, 0.0) <= 10.0 + 1e-3,
+                "went through {}",
+                heading
+            );
+            assert!(normalize(heading - previous) < 180.0);
+            previous = heading;
+        }
+        assert_close(previous, 10.0, 1e-2);
+
+        // Halfway between 350° and 10° is north, not south
+        let (state, _) = smooth(None, 350.0, 0.5);
+        let (_, heading) = smooth(Some(state), 10.0, 0.5);
+        assert!(distance(heading, 0.0) < 1e-3, "{}", heading);
+    }
+
+    #[test]
+    fn no_smoothing_follows_readings() {
+        let (state, _) = smooth(None, 120.0, 1.0);
+        let (_, heading) = smooth(Some(state), 300.0, 1.0);
+        assert_close(heading, 300.0, 1e-3);
+    }
+
+    #[test]
+    fn declination_wraps() {
+        let heading = with_declination(355.0, 10.0);
+        assert_close(heading.magnetic, 355.0, 1e-6);
+        assert_close(heading.true_north, 5.0, 1e-3);
+
+        assert_close(with_declination(5.0, -10.0).true_north, 355.0, 1e-3);
+        assert_close(with_declination(180.0, 0.0).true_north, 180.0, 1e-6);
+    }
+}
@@ -0,0 +1,302 @@
+//! Tilt angles and orientation from the gravity vector
+//!
+//! Axes follow the accelerometer: x towards the red LED, y towards the
+//! orange LED and z out of the components side of the board. The readings
+//! must be taken while the board is not accelerating, so that the
+//! accelerometer measures gravity only.
+
+use accelerometer::vector::F32x3;
+
+/// Tilt angles, all in degrees
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Angles {
+    /// Rotation around the x axis, positive with the orange edge raised,
+    /// from -90 to 90
+    pub pitch: f32,
+    /// Rotation around the y axis, positive with the red edge raised,
+    /// from -90 to 90
+    pub roll: f32,
+    /// Angle between the z axis and the vertical, from 0 (flat, face up) to
+    /// 180 (flat, face down)
+    pub tilt: f32,
+}
+
+/// Board orientation, named after the screen orientations of a handheld
+/// device whose top is the orange LED
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+    /// Lying flat, components side up
+    FaceUp,
+    /// Lying flat, components side down
+    FaceDown,
+    /// Upright, orange edge up
+    Portrait,
+    /// Upright, blue edge up
+    PortraitUpsideDown,
+    /// Upright, red edge up
+    LandscapeLeft,
+    /// Upright, green edge up
+    LandscapeRight,
+}
+
+impl Orientation {
+    const ALL: [Orientation; 6] = [
+        Orientation::FaceUp,
+        Orientation::FaceDown,
+        Orientation::Portrait,
+        Orientation::PortraitUpsideDown,
+        Orientation::LandscapeLeft,
+        Orientation::LandscapeRight,
+    ];
+
+    /// Component of the unit gravity vector pointing up in this orientation
+    fn alignment(self, g: &[f32; 3]) -> f32 {
+        match self {
+            Orientation::FaceUp => g[2],
+            Orientation::FaceDown => -g[2],
+            Orientation::Portrait => g[1],
+            Orientation::PortraitUpsideDown => -g[1],
+            Orientation::LandscapeLeft => g[0],
+            Orientation::LandscapeRight => -g[0],
+        }
+    }
+}
+
+/// Filtering settings
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Weight of a new reading in the low-pass filter, from just above 0
+    /// (heavy filtering) to 1 (no filtering)
+    pub filter: f32,
+    /// Minimum change of an angle, in degrees, before it is updated
+    pub dead_band: f32,
+    /// Angle, in degrees, by which the board must turn past the halfway
+    /// point between two orientations before the orientation changes
+    pub hysteresis: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            filter: 0.2,
+            dead_band: 0.5,
+            hysteresis: 10.0,
+        }
+    }
+}
+
+/// Inclinometer fed with accelerometer readings
+pub struct Inclinometer {
+    config: Config,
+    gravity: Option<[f32; 3]>,
+    angles: Angles,
+    orientation: Option<Orientation>,
+}
+
+impl Inclinometer {
+    /// Creates an inclinometer without any reading yet
+    pub fn new(config: Config) -> Self {
+        Inclinometer {
+            config,
+            gravity: None,
+            angles: Angles::default(),
+            orientation: None,
+        }
+    }
+
+    /// Feeds a reading, in any unit, and returns the new orientation if it
+    /// changed
+    ///
+    /// The first reading initializes the filter and always reports an
+    /// orientation.
+    pub fn update(&mut self, reading: F32x3) -> Option<Orientation> {
+        let reading = [reading.x, reading.y, reading.z];
+        let gravity = match self.gravity {
+            Some(mut gravity) => {
+                for (g, r) in gravity.iter_mut().zip(reading.iter()) {
+                    *g += self.config.filter * (r - *g);
+                }
+                gravity
+            }
+            None => reading,
+        };
+        self.gravity = Some(gravity);
+
+        let [x, y, z] = gravity;
+        let norm = libm::sqrtf(x * x + y * y + z * z);
+        if norm == 0.0 {
+            return None;
+        }
+
+        let angles = Angles {
+            pitch: libm::atan2f(y, libm::sqrtf(x * x + z * z)).to_degrees(),
+            roll: libm::atan2f(x, libm::sqrtf(y * y + z * z)).to_degrees(),
+            tilt: libm::acosf((z / norm).clamp(-1.0, 1.0)).to_degrees(),
+        };
+        let dead_band = self.config.dead_band;
+        let apply = |current: &mut f32, new: f32| {
+            if (new - *current).abs() >= dead_band {
+                *current = new;
+            }
+        };
+        apply(&mut self.angles.pitch, angles.pitch);
+        apply(&mut self.angles.roll, angles.roll);
+        apply(&mut self.angles.tilt, angles.tilt);
+
+        let unit = [x / norm, y / norm, z / norm];
+        let orientation = self.classify(&unit);
+        if orientation != self.orientation {
+            self.orientation = orientation;
+            orientation
+        } else {
+            None
+        }
+    }
+
+    /// Feeds a reading from an accelerometer
+    pub fn poll<A>(
+        &mut self,
+        accelerometer: &mut A,
+    ) -> Result<Option<Orientation>, accelerometer::Error<A::Error>>
+    where
+        A: accelerometer::Accelerometer,
+    {
+        let reading = accelerometer.accel_norm()?;
+        Ok(self.update(reading))
+    }
+
+    /// Returns the filtered tilt angles
+    pub fn angles(&self) -> Angles {
+        self.angles
+    }
+
+    /// Returns the current orientation, `None` before the first reading
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.orientation
+    }
+
+    /// Orientation for a unit gravity vector, keeping the current one
+    /// within the hysteresis
+    fn classify(&self, unit: &[f32; 3]) -> Option<Orientation> {
+        let best = Orientation::ALL.iter().copied().max_by(|a, b| {
+            a.alignment(unit)
+                .partial_cmp(&b.alignment(unit))
+                .unwrap_or(core::cmp::Ordering::Equal)
+        })?;
+
+        let current = match self.orientation {
+            Some(current) => current,
+            None => return Some(best),
+        };
+
+        // The halfway point between two orientations is 45° off either axis
+        let threshold = libm::cosf((45.0 - self.config.hysteresis).max(0.0).to_radians());
+        if best != current && best.alignment(unit) > threshold {
+            Some(best)
+        } else {
+            Some(current)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    /// Gravity reading with the orange edge raised by `angle` degrees
+    fn raised(angle: f32) -> F32x3 {
+        let angle = angle.to_radians();
+        F32x3::new(0.0, libm::sinf(angle), libm::cosf(angle))
+    }
+
+    fn unfiltered() -> Config {
+        Config {
+            filter: 1.0,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn first_reading_reports_orientation() {
+        let mut inclinometer = Inclinometer::new(Config::default());
+        assert_eq!(inclinometer.orientation(), None);
+        assert_eq!(inclinometer.update(raised(0.0)), Some(Orientation::FaceUp));
+        assert_eq!(inclinometer.update(raised(0.0)), None);
+        assert_eq!(inclinometer.orientation(), Some(Orientation::FaceUp));
+
+        // Also past the halfway point, where there is nothing to keep
+        let mut inclinometer = Inclinometer::new(Config::default());
+        assert_eq!(
+            inclinometer.update(raised(50.0)),
+            Some(Orientation::Portrait)
+        );
+
+        let mut inclinometer = Inclinometer::new(Config::default());
+        let down = F32x3::new(0.0, 0.0, -1.0);
+        assert_eq!(inclinometer.update(down), Some(Orientation::FaceDown));
+    }
+
+    #[test]
+    fn orientation_changes_past_hysteresis() {
+        let mut inclinometer = Inclinometer::new(unfiltered());
+        assert_eq!(inclinometer.update(raised(0.0)), Some(Orientation::FaceUp));
+
+        // The halfway point is 45°, the hysteresis adds 10°
+        assert_eq!(inclinometer.update(raised(40.0)), None);
+        assert_eq!(inclinometer.update(raised(50.0)), None);
+        assert_eq!(inclinometer.update(raised(54.0)), None);
+        assert_eq!(inclinometer.orientation(), Some(Orientation::FaceUp));
+        assert_eq!(
+            inclinometer.update(raised(56.0)),
+            Some(Orientation::Portrait)
+        );
+
+        // Same on the way back
+        assert_eq!(inclinometer.update(raised(40.0)), None);
+        assert_eq!(inclinometer.update(raised(36.0)), None);
+        assert_eq!(inclinometer.update(raised(34.0)), Some(Orientation::FaceUp));
+    }
+
+    #[test]
+    fn dead_band_suppresses_small_changes() {
+        let mut inclinometer = Inclinometer::new(unfiltered());
+        inclinometer.update(raised(10.0));
+        assert_close(inclinometer.angles().pitch, 10.0, 1e-3);
+        assert_close(inclinometer.angles().tilt, 10.0, 1e-3);
+
+        inclinometer.update(raised(10.3));
+        assert_close(inclinometer.angles().pitch, 10.0, 1e-3);
+        inclinometer.update(raised(9.7));
+        assert_close(inclinometer.angles().pitch, 10.0, 1e-3);
+
+        inclinometer.update(raised(10.8));
+        assert_close(inclinometer.angles().pitch, 10.8, 1e-3);
+        assert_close(inclinometer.angles().roll, 0.0, 1e-3);
+    }
+
+    #[test]
+    fn filter_smooths_gravity() {
+        let mut inclinometer = Inclinometer::new(Config {
+            filter: 0.5,
+            ..Config::default()
+        });
+        inclinometer.update(F32x3::new(0.0, 0.0, 1.0));
+        inclinometer.update(F32x3::new(1.0, 0.0, 0.0));
+
+        // Halfway between flat and the red edge raised
+        let angles = inclinometer.angles();
+        assert_close(angles.roll, 45.0, 1e-3);
+        assert_close(angles.tilt, 45.0, 1e-3);
+        assert_close(angles.pitch, 0.0, 1e-3);
+    }
+}
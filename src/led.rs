@@ -1,9 +1,32 @@
 //! On-board user LEDs
 
+use core::convert::Infallible;
+
 use crate::hal::prelude::*;
 
-use crate::hal::gpio::gpiod::{self, PD, PD12, PD13, PD14, PD15};
-use crate::hal::gpio::{Output, PushPull};
+use crate::hal::gpio::gpiod::{PD, PD12, PD13, PD14, PD15};
+use crate::hal::gpio::{Floating, Input, Output, PushPull};
+use crate::hal::stm32::GPIOD;
+
+use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin, ToggleableOutputPin};
+
+mod animator;
+mod pwm;
+mod rose;
+
+pub use self::animator::{AnimationTarget, Animator, Keyframe, Pattern, Priority};
+pub use self::pwm::{gamma, PwmLed, PwmLeds};
+
+/// GPIOD pin number of the LED in each slot of `Leds`, in `LedColor` order
+const PINS: [u8; 4] = [13, 12, 15, 14];
+
+/// Slots of the LEDs in clockwise order, starting at the top
+const CLOCKWISE: [usize; 4] = [
+    LedColor::Orange as usize,
+    LedColor::Red as usize,
+    LedColor::Blue as usize,
+    LedColor::Green as usize,
+];
 
 /// Top LED (orange)
 pub type LD3 = PD12<Output<PushPull>>;
@@ -35,20 +58,68 @@ pub struct Leds {
 }
 
 impl Leds {
-    pub fn new(gpiod: gpiod::Parts) -> Self {
-        let top = gpiod.pd12.into_push_pull_output();
-        let left = gpiod.pd13.into_push_pull_output();
-        let right = gpiod.pd14.into_push_pull_output();
-        let bottom = gpiod.pd15.into_push_pull_output();
+    /// Configures PD12 to PD15 as outputs
+    ///
+    /// The LEDs are stored in [`LedColor`] order, which is also clockwise
+    /// starting at the left LED.
+    pub fn new(
+        pd12: PD12<Input<Floating>>,
+        pd13: PD13<Input<Floating>>,
+        pd14: PD14<Input<Floating>>,
+        pd15: PD15<Input<Floating>>,
+    ) -> Self {
+        let top = pd12.into_push_pull_output();
+        let left = pd13.into_push_pull_output();
+        let right = pd15.into_push_pull_output();
+        let bottom = pd14.into_push_pull_output();
 
         Leds {
-            leds: [top.into(), left.into(), right.into(), bottom.into()],
+            leds: [left.into(), top.into(), right.into(), bottom.into()],
         }
     }
 
     pub fn iter_mut(&mut self) -> core::slice::IterMut<Led> {
         self.leds.iter_mut()
     }
+
+    /// Lights exactly the LEDs in `mask` in a single register write
+    ///
+    /// Bit `n` of the mask selects slot `n`, i.e. `1 << LedColor::Red as u8`
+    /// is the red LED.
+    pub fn set_mask(&mut self, mask: u8) {
+        self.apply(mask, !mask);
+    }
+
+    /// Turns on the LEDs in `on` and off the ones in `off` in a single
+    /// register write, leaving the others untouched
+    ///
+    /// An LED in both masks is turned on.
+    pub fn apply(&mut self, on: u8, off: u8) {
+        let mut bits = 0;
+        for (slot, &pin) in PINS.iter().enumerate() {
+            if on & (1 << slot) != 0 {
+                bits |= 1 << pin;
+            }
+            if off & (1 << slot) != 0 {
+                bits |= 1 << (pin + 16);
+            }
+        }
+
+        // Only the bits of the LED pins are written and BSRR writes are atomic
+        let gpiod = unsafe { &*GPIOD::ptr() };
+        gpiod.bsrr.write(|w| unsafe { w.bits(bits) });
+    }
+
+    /// Returns the mask of the LEDs currently lit, read in a single access
+    pub fn state(&self) -> u8 {
+        let gpiod = unsafe { &*GPIOD::ptr() };
+        let odr = gpiod.odr.read().bits();
+
+        PINS.iter()
+            .enumerate()
+            .filter(|&(_, &pin)| odr & (1 << pin) != 0)
+            .fold(0, |mask, (slot, _)| mask | (1 << slot))
+    }
 }
 
 impl core::ops::Deref for Leds {
@@ -127,10 +198,82 @@ impl Led {
 
     /// Toggles the LED
     pub fn toggle(&mut self) {
-        if let Ok(true) = self.pin.is_low() {
-            self.pin.set_high().ok();
+        if self.is_on() {
+            self.off();
         } else {
-            self.pin.set_low().ok();
+            self.on();
         }
     }
+
+    /// Returns `true` if the LED is lit, read back from the output register
+    pub fn is_on(&self) -> bool {
+        self.pin.is_set_high().unwrap_or(false)
+    }
+}
+
+impl OutputPin for Led {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.off();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.on();
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for Led {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.is_on())
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.is_on())
+    }
+}
+
+impl ToggleableOutputPin for Led {
+    type Error = Infallible;
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        Led::toggle(self);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_one::digital::ErrorType for Led {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_one::digital::OutputPin for Led {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.off();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.on();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_one::digital::StatefulOutputPin for Led {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_on())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_on())
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        Led::toggle(self);
+        Ok(())
+    }
 }
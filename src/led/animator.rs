@@ -0,0 +1,251 @@
+//! Non-blocking LED animations advanced from a periodic timer tick
+
+use core::f32::consts::PI;
+
+use super::{Leds, PwmLeds, CLOCKWISE};
+
+/// Number of priority levels, see [`Priority`]
+const LEVELS: usize = 4;
+
+/// LEDs an [`Animator`] can drive
+pub trait AnimationTarget {
+    /// Sets the LED in slot `index` to `brightness`, 0 (off) to 255 (fully on)
+    fn set_brightness(&mut self, index: usize, brightness: u8);
+}
+
+impl AnimationTarget for Leds {
+    /// Without PWM an LED is lit from half brightness on
+    fn set_brightness(&mut self, index: usize, brightness: u8) {
+        if brightness >= 128 {
+            self[index].on();
+        } else {
+            self[index].off();
+        }
+    }
+}
+
+impl AnimationTarget for PwmLeds {
+    fn set_brightness(&mut self, index: usize, brightness: u8) {
+        self[index].set_brightness(brightness);
+    }
+}
+
+/// One step of a user-defined sequence
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Brightness of each LED slot
+    pub brightness: [u8; 4],
+    /// Time until the next keyframe
+    pub duration_ms: u16,
+    /// Fade linearly towards the next keyframe instead of holding
+    pub fade: bool,
+}
+
+/// Animation patterns
+///
+/// LED sets are given as bit masks, bit `n` selecting slot `n` of the LEDs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+    /// Keeps the LEDs at a fixed brightness
+    Solid { mask: u8, brightness: u8 },
+    /// Switches the LEDs on for half of every period
+    Blink { mask: u8, period_ms: u16 },
+    /// Lights one LED at a time, going clockwise around the board
+    Chase { step_ms: u16 },
+    /// Fades the LEDs in and out smoothly
+    Breathe { mask: u8, period_ms: u16 },
+    /// Double pulse once per period
+    Heartbeat { mask: u8, period_ms: u16 },
+    /// Lights the LEDs once and then ends
+    Flash { mask: u8, duration_ms: u16 },
+    /// Plays keyframes in order, once or looping
+    Sequence {
+        frames: &'static [Keyframe],
+        repeat: bool,
+    },
+}
+
+fn masked(mask: u8, brightness: u8) -> [u8; 4] {
+    let mut frame = [0; 4];
+    for (i, led) in frame.iter_mut().enumerate() {
+        if mask & (1 << i) != 0 {
+            *led = brightness;
+        }
+    }
+    frame
+}
+
+impl Pattern {
+    /// Brightness of every LED `t_ms` after the start, `None` once finished
+    fn frame_at(&self, t_ms: u32) -> Option<[u8; 4]> {
+        match *self {
+            Pattern::Solid { mask, brightness } => Some(masked(mask, brightness)),
+            Pattern::Blink { mask, period_ms } => {
+                let period = u32::from(period_ms.max(1));
+                let on = t_ms % period < period / 2;
+                Some(masked(mask, if on { 255 } else { 0 }))
+            }
+            Pattern::Chase { step_ms } => {
+                let step = (t_ms / u32::from(step_ms.max(1))) as usize % CLOCKWISE.len();
+                Some(masked(1 << CLOCKWISE[step], 255))
+            }
+            Pattern::Breathe { mask, period_ms } => {
+                let period = u32::from(period_ms.max(1));
+                let phase = (t_ms % period) as f32 / period as f32;
+                let level = (1.0 - libm::cosf(2.0 * PI * phase)) / 2.0;
+                Some(masked(mask, (level * 255.0 + 0.5) as u8))
+            }
+            Pattern::Heartbeat { mask, period_ms } => {
+                // Two beats in the first 35% of the period, then rest
+                let period = u32::from(period_ms.max(1));
+                let permille = (t_ms % period) * 1000 / period;
+                let on = permille < 100 || (250..350).contains(&permille);
+                Some(masked(mask, if on { 255 } else { 0 }))
+            }
+            Pattern::Flash { mask, duration_ms } => {
+                if t_ms < u32::from(duration_ms) {
+                    Some(masked(mask, 255))
+                } else {
+                    None
+                }
+            }
+            Pattern::Sequence { frames, repeat } => sequence_at(frames, repeat, t_ms),
+        }
+    }
+}
+
+fn sequence_at(frames: &[Keyframe], repeat: bool, t_ms: u32) -> Option<[u8; 4]> {
+    let total: u32 = frames.iter().map(|f| u32::from(f.duration_ms)).sum();
+    if total == 0 || (!repeat && t_ms >= total) {
+        return None;
+    }
+
+    let mut t = t_ms % total;
+    for (i, frame) in frames.iter().enumerate() {
+        let duration = u32::from(frame.duration_ms);
+        if t >= duration {
+            t -= duration;
+            continue;
+        }
+
+        if !frame.fade {
+            return Some(frame.brightness);
+        }
+
+        let next = match frames.get(i + 1) {
+            Some(next) => next.brightness,
+            None if repeat => frames[0].brightness,
+            None => frame.brightness,
+        };
+        let mut out = [0; 4];
+        for (led, (&from, &to)) in out.iter_mut().zip(frame.brightness.iter().zip(next.iter())) {
+            let delta = i32::from(to) - i32::from(from);
+            *led = (i32::from(from) + delta * t as i32 / duration as i32) as u8;
+        }
+        return Some(out);
+    }
+
+    None
+}
+
+/// Animation priority levels
+///
+/// The highest priority with a running pattern drives the LEDs, lower ones
+/// keep running in the background and show again once it ends or is stopped.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Priority {
+    /// Idle or ambient patterns
+    Background = 0,
+    /// Normal operating status
+    Status = 1,
+    /// Short user feedback
+    Notification = 2,
+    /// Error indication
+    Error = 3,
+}
+
+#[derive(Copy, Clone)]
+struct Slot {
+    pattern: Pattern,
+    elapsed_ms: u32,
+}
+
+/// Plays [`Pattern`]s on the LEDs from a periodic tick
+///
+/// [`Animator::tick`] is meant to be called from a timer interrupt; it only
+/// updates the LED outputs and returns immediately.
+pub struct Animator<L> {
+    leds: L,
+    tick_ms: u16,
+    slots: [Option<Slot>; LEVELS],
+    shown: Option<[u8; 4]>,
+}
+
+impl<L> Animator<L>
+where
+    L: AnimationTarget,
+{
+    /// Creates an animator advancing by `tick_ms` on every tick
+    pub fn new(leds: L, tick_ms: u16) -> Self {
+        Animator {
+            leds,
+            tick_ms,
+            slots: [None; LEVELS],
+            shown: None,
+        }
+    }
+
+    /// Starts `pattern` at `priority`, replacing the pattern running there
+    pub fn play(&mut self, priority: Priority, pattern: Pattern) {
+        self.slots[priority as usize] = Some(Slot {
+            pattern,
+            elapsed_ms: 0,
+        });
+    }
+
+    /// Stops the pattern running at `priority`
+    pub fn stop(&mut self, priority: Priority) {
+        self.slots[priority as usize] = None;
+    }
+
+    /// Stops all patterns and turns the LEDs off on the next tick
+    pub fn stop_all(&mut self) {
+        self.slots = [None; LEVELS];
+    }
+
+    /// Returns `true` if a pattern is running at `priority`
+    pub fn is_playing(&self, priority: Priority) -> bool {
+        self.slots[priority as usize].is_some()
+    }
+
+    /// Advances every running pattern by one tick and updates the LEDs
+    pub fn tick(&mut self) {
+        let tick_ms = u32::from(self.tick_ms);
+        let mut frame = None;
+
+        for slot in self.slots.iter_mut().rev() {
+            if let Some(running) = slot {
+                match running.pattern.frame_at(running.elapsed_ms) {
+                    Some(f) => {
+                        running.elapsed_ms = running.elapsed_ms.wrapping_add(tick_ms);
+                        frame = frame.or(Some(f));
+                    }
+                    None => *slot = None,
+                }
+            }
+        }
+
+        let frame = frame.unwrap_or([0; 4]);
+        if self.shown != Some(frame) {
+            for (i, &brightness) in frame.iter().enumerate() {
+                self.leds.set_brightness(i, brightness);
+            }
+            self.shown = Some(frame);
+        }
+    }
+
+    /// Stops animating and returns the LEDs
+    pub fn release(self) -> L {
+        self.leds
+    }
+}
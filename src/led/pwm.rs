@@ -0,0 +1,253 @@
+//! PWM brightness control of the user LEDs on TIM4
+//!
+//! PD12 to PD15 are TIM4 channels 1 to 4 on alternate function 2, so the
+//! LEDs can be switched between plain GPIO outputs and PWM outputs without
+//! giving up the pins.
+
+use crate::hal::gpio::gpiod::{PD12, PD13, PD14, PD15};
+use crate::hal::gpio::{Floating, Input};
+use crate::hal::rcc::Clocks;
+use crate::hal::stm32::{GPIOD, RCC, TIM4};
+
+use super::{LedColor, Leds, PINS};
+
+/// PWM frequency, high enough to avoid visible flicker
+const PWM_FREQUENCY: u32 = 1_000;
+
+/// Number of timer counts per PWM period
+const PERIOD: u32 = 4096;
+
+/// Gamma of the perceived brightness curve
+const GAMMA: f32 = 2.2;
+
+/// One of the on-board user LEDs driven by a TIM4 channel
+pub struct PwmLed {
+    channel: u8,
+}
+
+impl PwmLed {
+    /// Duty value turning the LED fully on
+    pub const MAX_DUTY: u16 = PERIOD as u16;
+
+    /// Sets the raw duty cycle, from 0 to [`PwmLed::MAX_DUTY`]
+    pub fn set_duty(&mut self, duty: u16) {
+        let duty = u32::from(duty.min(Self::MAX_DUTY));
+        // The channel is owned by this LED, TIM4 is owned by `PwmLeds`
+        let tim = unsafe { &*TIM4::ptr() };
+
+        match self.channel {
+            1 => tim.ccr1.write(|w| unsafe { w.bits(duty) }),
+            2 => tim.ccr2.write(|w| unsafe { w.bits(duty) }),
+            3 => tim.ccr3.write(|w| unsafe { w.bits(duty) }),
+            _ => tim.ccr4.write(|w| unsafe { w.bits(duty) }),
+        }
+    }
+
+    /// Returns the raw duty cycle
+    pub fn duty(&self) -> u16 {
+        let tim = unsafe { &*TIM4::ptr() };
+
+        let duty = match self.channel {
+            1 => tim.ccr1.read().bits(),
+            2 => tim.ccr2.read().bits(),
+            3 => tim.ccr3.read().bits(),
+            _ => tim.ccr4.read().bits(),
+        };
+        duty as u16
+    }
+
+    /// Sets the duty cycle in percent, saturating at 100
+    pub fn set_percent(&mut self, percent: u8) {
+        let percent = u32::from(percent.min(100));
+        self.set_duty((percent * PERIOD / 100) as u16);
+    }
+
+    /// Sets the duty cycle linearly from 0 (off) to 255 (fully on)
+    pub fn set_level(&mut self, level: u8) {
+        self.set_duty((u32::from(level) * PERIOD / 255) as u16);
+    }
+
+    /// Sets the gamma-corrected brightness from 0 (off) to 255 (fully on)
+    ///
+    /// Equal steps of `brightness` are perceived as equal steps of light,
+    /// which makes fades look smooth.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.set_duty(gamma(brightness));
+    }
+
+    /// Turns the LED fully on
+    pub fn on(&mut self) {
+        self.set_duty(Self::MAX_DUTY);
+    }
+
+    /// Turns the LED off
+    pub fn off(&mut self) {
+        self.set_duty(0);
+    }
+}
+
+/// Duty cycle matching a perceived brightness from 0 to 255
+pub fn gamma(brightness: u8) -> u16 {
+    let linear = f32::from(brightness) / 255.0;
+    (libm::powf(linear, GAMMA) * PERIOD as f32 + 0.5) as u16
+}
+
+/// The on-board user LEDs driven by TIM4 PWM
+pub struct PwmLeds {
+    leds: Leds,
+    tim: TIM4,
+    pwm: [PwmLed; 4],
+}
+
+impl PwmLeds {
+    /// Initializes the LEDs as TIM4 PWM outputs, all off
+    pub fn new(
+        pd12: PD12<Input<Floating>>,
+        pd13: PD13<Input<Floating>>,
+        pd14: PD14<Input<Floating>>,
+        pd15: PD15<Input<Floating>>,
+        tim4: TIM4,
+        clocks: Clocks,
+    ) -> Self {
+        Leds::new(pd12, pd13, pd14, pd15).into_pwm(tim4, clocks)
+    }
+
+    /// Stops the PWM and switches the pins back to GPIO outputs
+    ///
+    /// Returns the GPIO form of the LEDs, all off, and the timer.
+    pub fn into_gpio(self) -> (Leds, TIM4) {
+        self.tim.cr1.write(|w| unsafe { w.bits(0) });
+        self.tim.ccer.write(|w| unsafe { w.bits(0) });
+
+        let mut leds = self.leds;
+        for led in leds.iter_mut() {
+            led.off();
+        }
+        set_pin_mode(0b01);
+
+        (leds, self.tim)
+    }
+
+    pub fn iter_mut(&mut self) -> core::slice::IterMut<PwmLed> {
+        self.pwm.iter_mut()
+    }
+}
+
+impl Leds {
+    /// Switches the LEDs to TIM4 PWM outputs, all off
+    pub fn into_pwm(self, tim4: TIM4, clocks: Clocks) -> PwmLeds {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.apb1enr.modify(|_, w| w.tim4en().set_bit());
+
+        // Timers on APB1 run at twice PCLK1 unless the APB1 prescaler is 1
+        let timclk = if clocks.ppre1() == 1 {
+            clocks.pclk1().0
+        } else {
+            clocks.pclk1().0 * 2
+        };
+        let psc = (timclk / (PWM_FREQUENCY * PERIOD)).max(1) - 1;
+
+        tim4.cr1.write(|w| unsafe { w.bits(0) });
+        tim4.psc.write(|w| unsafe { w.bits(psc) });
+        tim4.arr.write(|w| unsafe { w.bits(PERIOD - 1) });
+        tim4.ccr1.write(|w| unsafe { w.bits(0) });
+        tim4.ccr2.write(|w| unsafe { w.bits(0) });
+        tim4.ccr3.write(|w| unsafe { w.bits(0) });
+        tim4.ccr4.write(|w| unsafe { w.bits(0) });
+
+        // PWM mode 1 with preload on all four channels
+        const PWM1_PRELOAD: u32 = 0b0110_1000;
+        tim4.ccmr1_output()
+            .write(|w| unsafe { w.bits((PWM1_PRELOAD << 8) | PWM1_PRELOAD) });
+        tim4.ccmr2_output()
+            .write(|w| unsafe { w.bits((PWM1_PRELOAD << 8) | PWM1_PRELOAD) });
+        // CC1E to CC4E, active high
+        tim4.ccer.write(|w| unsafe { w.bits(0x1111) });
+
+        // Load the prescaler (UG), then run (CEN) with auto-reload preload (ARPE)
+        tim4.egr.write(|w| unsafe { w.bits(0x01) });
+        tim4.cr1.write(|w| unsafe { w.bits(0x81) });
+
+        set_pin_mode(0b10);
+
+        // PD12 is channel 1, up to PD15 on channel 4
+        let pwm = [
+            PwmLed {
+                channel: PINS[0] - 11,
+            },
+            PwmLed {
+                channel: PINS[1] - 11,
+            },
+            PwmLed {
+                channel: PINS[2] - 11,
+            },
+            PwmLed {
+                channel: PINS[3] - 11,
+            },
+        ];
+
+        PwmLeds {
+            leds: self,
+            tim: tim4,
+            pwm,
+        }
+    }
+}
+
+/// Switches PD12 to PD15 between output (0b01) and alternate function (0b10)
+///
+/// Only the bits of the LED pins are touched, in a single write per register.
+fn set_pin_mode(mode: u32) {
+    // The LED pins are owned by `Leds` and `PwmLeds`
+    let gpiod = unsafe { &*GPIOD::ptr() };
+
+    // AF2 (TIM4) on PD12 to PD15
+    gpiod
+        .afrh
+        .modify(|r, w| unsafe { w.bits((r.bits() & 0x0000_FFFF) | 0x2222_0000) });
+    gpiod
+        .moder
+        .modify(|r, w| unsafe { w.bits((r.bits() & 0x00FF_FFFF) | mode * 0x5500_0000) });
+}
+
+impl core::ops::Deref for PwmLeds {
+    type Target = [PwmLed];
+
+    fn deref(&self) -> &[PwmLed] {
+        &self.pwm
+    }
+}
+
+impl core::ops::DerefMut for PwmLeds {
+    fn deref_mut(&mut self) -> &mut [PwmLed] {
+        &mut self.pwm
+    }
+}
+
+impl core::ops::Index<usize> for PwmLeds {
+    type Output = PwmLed;
+
+    fn index(&self, i: usize) -> &PwmLed {
+        &self.pwm[i]
+    }
+}
+
+impl core::ops::Index<LedColor> for PwmLeds {
+    type Output = PwmLed;
+
+    fn index(&self, c: LedColor) -> &PwmLed {
+        &self.pwm[c as usize]
+    }
+}
+
+impl core::ops::IndexMut<usize> for PwmLeds {
+    fn index_mut(&mut self, i: usize) -> &mut PwmLed {
+        &mut self.pwm[i]
+    }
+}
+
+impl core::ops::IndexMut<LedColor> for PwmLeds {
+    fn index_mut(&mut self, c: LedColor) -> &mut PwmLed {
+        &mut self.pwm[c as usize]
+    }
+}
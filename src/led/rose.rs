@@ -0,0 +1,90 @@
+//! Direction indicator on the cross of user LEDs
+//!
+//! Angles are in degrees, clockwise from the top (orange) LED, so 90° points
+//! at the right (red) LED. Vectors have x pointing at the red LED and y at
+//! the orange one, matching the accelerometer axes.
+
+use super::{Leds, PwmLeds, CLOCKWISE};
+
+/// Angle of a vector in degrees, `None` for the zero vector
+fn vector_angle(x: f32, y: f32) -> Option<f32> {
+    if x == 0.0 && y == 0.0 {
+        None
+    } else {
+        Some(libm::atan2f(x, y).to_degrees())
+    }
+}
+
+/// Position of an angle around the cross, from 0 (top) up to, but
+/// excluding, 4 (top again)
+fn position(angle: f32) -> f32 {
+    let position = (angle / 90.0) % 4.0;
+    let position = if position < 0.0 {
+        position + 4.0
+    } else {
+        position
+    };
+    // Tiny negative angles round up to exactly 4
+    if position >= 4.0 {
+        0.0
+    } else {
+        position
+    }
+}
+
+impl Leds {
+    /// Lights only the LED nearest to `angle`
+    pub fn point_to(&mut self, angle: f32) {
+        let nearest = (position(angle) + 0.5) as usize % CLOCKWISE.len();
+        self.set_mask(1 << CLOCKWISE[nearest]);
+    }
+
+    /// Lights only the LED nearest to the direction of `(x, y)`
+    ///
+    /// All LEDs are turned off for the zero vector.
+    pub fn point_to_vector(&mut self, x: f32, y: f32) {
+        match vector_angle(x, y) {
+            Some(angle) => self.point_to(angle),
+            None => self.set_mask(0),
+        }
+    }
+}
+
+impl PwmLeds {
+    /// Shows `angle` by blending the two LEDs it falls between
+    ///
+    /// The brightness of each LED falls linearly with its distance to the
+    /// angle, with gamma correction so that the perceived total stays
+    /// constant while the angle sweeps around.
+    pub fn point_to(&mut self, angle: f32) {
+        let position = position(angle);
+        let before = position as usize % CLOCKWISE.len();
+        let after = (before + 1) % CLOCKWISE.len();
+        let weight = position - before as f32;
+
+        for (i, &slot) in CLOCKWISE.iter().enumerate() {
+            let level = if i == before {
+                1.0 - weight
+            } else if i == after {
+                weight
+            } else {
+                0.0
+            };
+            self[slot].set_brightness((level * 255.0 + 0.5) as u8);
+        }
+    }
+
+    /// Shows the direction of `(x, y)` by blending the two nearest LEDs
+    ///
+    /// All LEDs are turned off for the zero vector.
+    pub fn point_to_vector(&mut self, x: f32, y: f32) {
+        match vector_angle(x, y) {
+            Some(angle) => self.point_to(angle),
+            None => {
+                for led in self.iter_mut() {
+                    led.off();
+                }
+            }
+        }
+    }
+}
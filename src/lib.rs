@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(non_camel_case_types)]
 
 pub use stm32f4xx_hal as hal;
@@ -12,4 +12,18 @@ pub use cortex_m::*;
 pub use cortex_m_rt::*;
 
 pub mod accelerometer;
+pub mod board;
+pub mod bus;
+pub mod button;
+pub mod calibration;
+pub mod clocks;
+pub mod compass;
+pub mod exti;
+pub mod fusion;
+pub mod gyroscope;
+pub mod heading;
+pub mod inclinometer;
 pub mod led;
+pub mod revision;
+
+pub use crate::board::Board;
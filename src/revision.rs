@@ -0,0 +1,115 @@
+//! Board revision and MEMS sensor variant detection
+//!
+//! Boards up to MB1115 revision C carry an LSM303DLHC e-compass and an L3GD20
+//! gyroscope. From revision D on they ship an LSM303AGR and an I3G4250D
+//! instead. Both generations are told apart by their WHO_AM_I registers.
+
+use embedded_hal::blocking::i2c::WriteRead;
+
+/// I2C address of the magnetometer die of both e-compass variants
+const MAG_ADDRESS: u8 = 0x1E;
+
+/// LSM303AGR WHO_AM_I_M register and expected value
+const LSM303AGR_WHO_AM_I_M: (u8, u8) = (0x4F, 0x40);
+
+/// LSM303DLHC IRA_REG_M register and expected value (ASCII 'H')
+const LSM303DLHC_IRA_REG_M: (u8, u8) = (0x0A, 0x48);
+
+/// Errors while probing the sensors
+#[derive(Debug)]
+pub enum Error<E> {
+    /// Bus error
+    Bus(E),
+    /// Neither e-compass variant answered on I2C1
+    UnknownEcompass,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Error::Bus(error)
+    }
+}
+
+/// E-compass (accelerometer and magnetometer) part on I2C1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EcompassVariant {
+    /// LSM303DLHC
+    Lsm303dlhc,
+    /// LSM303AGR
+    Lsm303agr,
+}
+
+/// Gyroscope part on SPI1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GyroscopeVariant {
+    /// L3GD20
+    L3gd20,
+    /// I3G4250D
+    I3g4250d,
+}
+
+impl GyroscopeVariant {
+    /// Maps the content of the WHO_AM_I register to a gyroscope part
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0xD4 => Some(GyroscopeVariant::L3gd20),
+            0xD3 => Some(GyroscopeVariant::I3g4250d),
+            _ => None,
+        }
+    }
+}
+
+/// Hardware revision of the board
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BoardRevision {
+    /// MB1115 up to revision C (LSM303DLHC, L3GD20)
+    MB1115C,
+    /// MB1115 revision D and later (LSM303AGR, I3G4250D)
+    MB1115D,
+    /// Sensor combination that was never shipped by ST
+    Unknown,
+}
+
+/// MEMS sensors found on the board
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorSet {
+    /// E-compass part
+    pub ecompass: EcompassVariant,
+    /// Gyroscope part
+    pub gyroscope: GyroscopeVariant,
+}
+
+impl SensorSet {
+    /// Board revision matching this sensor combination
+    pub fn revision(&self) -> BoardRevision {
+        match (self.ecompass, self.gyroscope) {
+            (EcompassVariant::Lsm303dlhc, GyroscopeVariant::L3gd20) => BoardRevision::MB1115C,
+            (EcompassVariant::Lsm303agr, GyroscopeVariant::I3g4250d) => BoardRevision::MB1115D,
+            _ => BoardRevision::Unknown,
+        }
+    }
+}
+
+/// Identifies the e-compass on I2C1
+pub fn probe_ecompass<I2C, E>(i2c: &mut I2C) -> Result<EcompassVariant, Error<E>>
+where
+    I2C: WriteRead<Error = E>,
+{
+    let mut id = [0u8];
+
+    // Both accelerometer dies answer 0x33 at 0x0F, so only the magnetometer
+    // tells them apart. The LSM303DLHC magnetometer has no WHO_AM_I_M and
+    // may NACK the read.
+    let (register, value) = LSM303AGR_WHO_AM_I_M;
+    if i2c.write_read(MAG_ADDRESS, &[register], &mut id).is_ok() && id[0] == value {
+        return Ok(EcompassVariant::Lsm303agr);
+    }
+
+    let (register, value) = LSM303DLHC_IRA_REG_M;
+    i2c.write_read(MAG_ADDRESS, &[register], &mut id)?;
+    if id[0] == value {
+        return Ok(EcompassVariant::Lsm303dlhc);
+    }
+
+    Err(Error::UnknownEcompass)
+}